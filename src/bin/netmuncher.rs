@@ -8,22 +8,31 @@
 //
 
 extern crate clap;
-extern crate error_chain;
 extern crate netmuncher;
 
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::process;
 
-use error_chain::ChainedError;
 use netmuncher::circuit::{Circuit, DotSerializer, KicadNetListSerializer, SerializeCircuit};
+use netmuncher::deps::Dependencies;
+use netmuncher::diagnostic::{Diagnostic, MessageFormat};
 use netmuncher::diagram::Diagram;
+use netmuncher::erc::ErcMatrix;
+use netmuncher::error::{Error, ErrorKind};
+use netmuncher::lint;
+use netmuncher::manifest::{Manifest, MANIFEST_NAME};
+use netmuncher::options::Options;
+use netmuncher::system::System;
 
 fn main() {
     let matches = clap::App::new("netmuncher")
         .version("0.5.0")
         .author("John DiSanti")
         .about("Hierarchical circuit definition to netlist transformer")
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
         .arg(
             clap::Arg::with_name("INPUT")
                 .help("input source file")
@@ -45,78 +54,346 @@ fn main() {
                 .value_name("FORMAT")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("LIB_PATH")
+                .help("add a directory to search for required files")
+                .short("L")
+                .long("lib-path")
+                .value_name("DIR")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("TOP")
+                .help("component to compile instead of Main")
+                .long("top")
+                .value_name("COMPONENT")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("MESSAGE_FORMAT")
+                .help("how to write errors and warnings")
+                .long("message-format")
+                .value_name("FORMAT")
+                .possible_values(&["human", "json", "sarif"])
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("DENY_WARNINGS")
+                .help("fail on warnings that aren't allowed by a netmuncher:allow comment")
+                .short("W")
+                .long("deny-warnings")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("LINT")
+                .help("set the level of a lint, like unused-net=deny")
+                .long("lint")
+                .value_name("LINT=LEVEL")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|s| lint::parse_setting(&s).map(|_| ()).map_err(|e| e.to_string()))
+                .global(true),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("build")
+                .about("generates every output listed in the project manifest")
+                .arg(
+                    clap::Arg::with_name("MANIFEST")
+                        .help("project manifest, instead of searching for netmuncher.toml")
+                        .long("manifest-path")
+                        .value_name("PATH")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("erc-matrix")
+                .about("prints the ERC results for each pair of pin types and each net check")
+                .arg(
+                    clap::Arg::with_name("INPUT")
+                        .help("source file whose erc blocks to include, along with its project's"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("deps")
+                .about("prints the components the circuit uses and what each one instantiates")
+                .arg(
+                    clap::Arg::with_name("INPUT")
+                        .help("input source file")
+                        .required(true),
+                ),
+        )
         .get_matches();
 
+    let mut options = Options::new();
+    if let Some(lib_paths) = matches.values_of("LIB_PATH") {
+        options.lib_paths.extend(lib_paths.map(PathBuf::from));
+    }
+    options.top = matches.value_of("TOP").map(String::from);
+    options.deny_warnings = matches.is_present("DENY_WARNINGS");
+    if let Some(settings) = matches.values_of("LINT") {
+        options.lint_levels.extend(settings.map(|s| lint::parse_setting(s).unwrap()));
+    }
+    let message_format = matches.value_of("MESSAGE_FORMAT");
+
+    if let Some(build_matches) = matches.subcommand_matches("build") {
+        apply_global_args(build_matches, &mut options);
+        let message_format = build_matches.value_of("MESSAGE_FORMAT").or(message_format);
+        let mut reporter = Reporter::new(message_format);
+        build(build_matches.value_of("MANIFEST"), options, &mut reporter);
+        reporter.finish();
+        return;
+    }
+    if let Some(erc_matches) = matches.subcommand_matches("erc-matrix") {
+        apply_global_args(erc_matches, &mut options);
+        let message_format = erc_matches.value_of("MESSAGE_FORMAT").or(message_format);
+        let reporter = Reporter::new(message_format);
+        print_erc_matrix(erc_matches.value_of("INPUT"), &options, &reporter);
+        reporter.finish();
+        return;
+    }
+    if let Some(deps_matches) = matches.subcommand_matches("deps") {
+        apply_global_args(deps_matches, &mut options);
+        let message_format = deps_matches.value_of("MESSAGE_FORMAT").or(message_format);
+        let reporter = Reporter::new(message_format);
+        print_deps(deps_matches.value_of("INPUT").unwrap(), &options, &reporter);
+        reporter.finish();
+        return;
+    }
+
+    let mut reporter = Reporter::new(message_format);
+
     let input_file_name = matches.value_of("INPUT").unwrap();
-    let output_file_name = matches.value_of("OUTPUT");
     let format = matches.value_of("FORMAT").unwrap_or("kicad");
+    let output_file_name = match matches.value_of("OUTPUT") {
+        Some(output_file_name) => output_file_name.into(),
+        None if format == "diagram_json" => format!("{}.json", input_file_name),
+        None => format!("{}.net", input_file_name),
+    };
 
     match format {
-        "dot" | "kicad" => compile_circuit(input_file_name, output_file_name, format),
-        "diagram_json" => compile_diagram(input_file_name, output_file_name, format),
-        _ => {
-            println!("Unknown output format: {}", format);
-            process::exit(1);
+        "dot" | "kicad" => {
+            let circuit = compile_circuit(input_file_name, &options, &mut reporter);
+            write_circuit(&circuit, &output_file_name, format, &reporter);
+        }
+        "diagram_json" => {
+            let diagram = compile_diagram(input_file_name, &options, &reporter);
+            write_diagram(diagram, &output_file_name, &reporter);
+        }
+        _ => reporter.fail_with(format!("Unknown output format: {}", format)),
+    }
+    reporter.finish();
+}
+
+/// Applies global arguments that were given after a subcommand
+fn apply_global_args(matches: &clap::ArgMatches, options: &mut Options) {
+    if let Some(lib_paths) = matches.values_of("LIB_PATH") {
+        options.lib_paths.extend(lib_paths.map(PathBuf::from));
+    }
+    if let Some(top) = matches.value_of("TOP") {
+        options.top = Some(top.into());
+    }
+    options.deny_warnings |= matches.is_present("DENY_WARNINGS");
+    if let Some(settings) = matches.values_of("LINT") {
+        options.lint_levels.extend(settings.map(|s| lint::parse_setting(s).unwrap()));
+    }
+}
+
+/// Writes errors and warnings in the chosen message format. Warnings are collected until
+/// the end of the run, since some formats write everything as one document.
+struct Reporter {
+    format: MessageFormat,
+    warnings: Vec<Diagnostic>,
+}
+
+impl Reporter {
+    fn new(format: Option<&str>) -> Reporter {
+        Reporter {
+            format: format.map_or(MessageFormat::Human, |f| f.parse().unwrap()),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, warnings: &[Diagnostic]) {
+        self.warnings.extend(warnings.iter().cloned());
+    }
+
+    /// Prints progress, which goes to stderr when stdout is for other tools to read
+    fn status(&self, message: &str) {
+        match self.format {
+            MessageFormat::Human => println!("{}", message),
+            _ => eprintln!("{}", message),
+        }
+    }
+
+    fn fail(&self, err: Error) -> ! {
+        print!("{}", self.format.format(&self.warnings, Some(&err)));
+        process::exit(1);
+    }
+
+    fn fail_with(&self, message: String) -> ! {
+        self.fail(ErrorKind::NetmuncherError(message).into())
+    }
+
+    fn finish(&self) {
+        print!("{}", self.format.format(&self.warnings, None));
+    }
+}
+
+/// Finds the manifest in the current directory or its parents, by a path relative to the
+/// current directory
+fn find_manifest() -> Result<Option<Manifest>, Error> {
+    let mut dir = PathBuf::new();
+    for _ in env::current_dir()?.ancestors() {
+        let path = dir.join(MANIFEST_NAME);
+        if path.is_file() {
+            return Manifest::load(path).map(Some);
         }
+        dir.push("..");
     }
+    Ok(None)
 }
 
-fn compile_circuit(input_file_name: &str, optional_output_file_name: Option<&str>, format: &str) {
-    let circuit = match Circuit::compile(input_file_name) {
-        Ok(circuit) => circuit,
-        Err(err) => {
-            println!("{}", err.display_chain().to_string());
-            process::exit(1);
+fn build(manifest_path: Option<&str>, mut options: Options, reporter: &mut Reporter) {
+    let manifest = match manifest_path {
+        Some(path) => Manifest::load(path).map(Some),
+        None => find_manifest(),
+    };
+    let manifest = match manifest {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => reporter.fail_with(format!(
+            "Cannot find {} in this directory or its parents",
+            MANIFEST_NAME
+        )),
+        Err(err) => reporter.fail(err),
+    };
+    let outputs = manifest.outputs();
+    if outputs.is_empty() && manifest.boards.is_empty() {
+        reporter.fail_with(format!("{} doesn't list any outputs", MANIFEST_NAME));
+    }
+    if !manifest.boards.is_empty() {
+        build_system(&manifest, &options, reporter);
+    }
+    if outputs.is_empty() {
+        return;
+    }
+    let entry = match manifest.entry_path() {
+        Ok(entry) => entry.to_string_lossy().into_owned(),
+        Err(err) => reporter.fail(err),
+    };
+    options.manifest = Some(manifest);
+
+    let needs_circuit = outputs.iter().any(|output| output.format != "diagram_json");
+    let circuit = if needs_circuit {
+        Some(compile_circuit(&entry, &options, reporter))
+    } else {
+        None
+    };
+    for output in outputs {
+        let path = output.path.to_string_lossy();
+        match output.format.as_str() {
+            "diagram_json" => {
+                write_diagram(compile_diagram(&entry, &options, reporter), &path, reporter)
+            }
+            format => write_circuit(circuit.as_ref().unwrap(), &path, format, reporter),
         }
+        reporter.status(&format!("Wrote {}", path));
+    }
+}
+
+fn print_erc_matrix(input_file_name: Option<&str>, options: &Options, reporter: &Reporter) {
+    let matrix = match input_file_name {
+        Some(input_file_name) => ErcMatrix::for_file(input_file_name, options),
+        None => find_manifest()
+            .and_then(|manifest| ErcMatrix::for_project(manifest.as_ref(), options)),
     };
+    match matrix {
+        Ok(matrix) => print!("{}", matrix.report()),
+        Err(err) => reporter.fail(err),
+    }
+}
 
-    let output_file_name = optional_output_file_name
-        .map(|n| String::from(n))
-        .unwrap_or_else(|| format!("{}.net", input_file_name));
+fn print_deps(input_file_name: &str, options: &Options, reporter: &Reporter) {
+    match Dependencies::for_file(input_file_name, options) {
+        Ok(deps) => print!("{}", deps.report()),
+        Err(err) => reporter.fail(err),
+    }
+}
+
+fn build_system(manifest: &Manifest, options: &Options, reporter: &mut Reporter) {
+    let system = match System::compile(manifest, options) {
+        Ok(system) => system,
+        Err(err) => reporter.fail(err),
+    };
+    for board in &system.boards {
+        reporter.warn(&board.circuit.warnings);
+    }
+    reporter.warn(&system.warnings);
+    for (name, path) in manifest.board_outputs() {
+        let board = system.boards.iter().find(|b| b.name == name).unwrap();
+        let path = path.to_string_lossy();
+        write_circuit(&board.circuit, &path, "kicad", reporter);
+        reporter.status(&format!("Wrote {}", path));
+    }
+    if let Some(path) = manifest.wiring_report_path() {
+        let path = path.to_string_lossy();
+        write_file(&path, system.wiring_report().into_bytes(), reporter);
+        reporter.status(&format!("Wrote {}", path));
+    }
+}
+
+fn compile_circuit(input_file_name: &str, options: &Options, reporter: &mut Reporter) -> Circuit {
+    match Circuit::compile_with_options(input_file_name, options) {
+        Ok(circuit) => {
+            reporter.warn(&circuit.warnings);
+            circuit
+        }
+        Err(err) => reporter.fail(err),
+    }
+}
 
+fn write_circuit(circuit: &Circuit, output_file_name: &str, format: &str, reporter: &Reporter) {
     let output_result = match format {
-        "kicad" => KicadNetListSerializer::new().serialize(&circuit),
-        "dot" => DotSerializer::new().serialize(&circuit),
+        "kicad" => KicadNetListSerializer::new().serialize(circuit),
+        "dot" => DotSerializer::new().serialize(circuit),
         _ => unreachable!(),
     };
     let output = match output_result {
         Ok(out) => out,
-        Err(err) => {
-            println!("Failed to serialize: {}", err);
-            process::exit(1);
-        }
+        Err(err) => reporter.fail_with(format!("Failed to serialize: {}", err)),
     };
 
-    write_file(&output_file_name, output);
+    write_file(output_file_name, output, reporter);
 }
 
-fn compile_diagram(input_file_name: &str, optional_output_file_name: Option<&str>, _format: &str) {
-    let diagram = match Diagram::compile(input_file_name) {
+fn compile_diagram(input_file_name: &str, options: &Options, reporter: &Reporter) -> Diagram {
+    match Diagram::compile_with_options(input_file_name, options) {
         Ok(diagram) => diagram,
-        Err(err) => {
-            println!("{}", err.display_chain().to_string());
-            process::exit(1);
-        }
-    };
-
-    let output_file_name = optional_output_file_name
-        .map(|n| String::from(n))
-        .unwrap_or_else(|| format!("{}.json", input_file_name));
+        Err(err) => reporter.fail(err),
+    }
+}
 
+fn write_diagram(diagram: Diagram, output_file_name: &str, reporter: &Reporter) {
     let output = diagram.to_json_bytes().unwrap();
-    write_file(&output_file_name, output);
+    write_file(output_file_name, output, reporter);
 }
 
-fn write_file(file_name: &str, contents: Vec<u8>) {
+fn write_file(file_name: &str, contents: Vec<u8>, reporter: &Reporter) {
+    if let Some(dir) = Path::new(file_name).parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            reporter.fail_with(format!("Failed to create output directory: {}", err));
+        }
+    }
     let mut file = match File::create(file_name) {
         Ok(file) => file,
-        Err(err) => {
-            println!("Failed to create output file: {}", err);
-            process::exit(1);
-        }
+        Err(err) => reporter.fail_with(format!("Failed to create output file: {}", err)),
     };
     if let Err(err) = file.write_all(&contents) {
-        println!("Failed to write output file: {}", err);
-        process::exit(1);
+        reporter.fail_with(format!("Failed to write output file: {}", err));
     }
 }
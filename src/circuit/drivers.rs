@@ -0,0 +1,148 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Checks each net of the instantiated circuit by what drives it and what it drives, which
+//! the ERC can't tell by comparing its pins two at a time
+
+use std::collections::BTreeMap;
+
+use circuit::{Circuit, Net, Node};
+use diagnostic::{Diagnostic, Severity};
+use erc::{ErcMatrix, NetCheck};
+use error;
+use options::ErcLevel;
+use parse::component::PinType;
+use parse::source::Sources;
+
+/// Whether a pin can drive a net
+fn is_driver(pin_type: PinType) -> bool {
+    use parse::component::PinType::*;
+    match pin_type {
+        Output | PowerOut | Tristate | Bidirectional => true,
+        Input | PowerIn | Passive | NoConnect => false,
+    }
+}
+
+/// Whether a pin drives its net all of the time, so can't share it with another such pin
+fn always_drives(pin_type: PinType) -> bool {
+    pin_type == PinType::Output || pin_type == PinType::PowerOut
+}
+
+fn is_input(pin_type: PinType) -> bool {
+    pin_type == PinType::Input || pin_type == PinType::PowerIn
+}
+
+/// Checks every net, failing with all of the findings that are errors, or returning the
+/// warnings otherwise
+pub fn check(
+    sources: &Sources,
+    circuit: &Circuit,
+    erc: &ErcMatrix,
+) -> error::Result<Vec<Diagnostic>> {
+    let pins: Vec<Vec<&Node>> = circuit.nets.iter().map(Net::distinct_pins).collect();
+    let sourced = sourced_nets(&pins);
+
+    let mut errors = error::Errors::new();
+    let mut warnings = Vec::new();
+    for (index, net) in circuit.nets.iter().enumerate() {
+        let nodes = &pins[index];
+        let inputs: Vec<&Node> = nodes.iter().cloned().filter(|n| is_input(n.pin_type)).collect();
+        let drivers: Vec<&Node> = nodes
+            .iter()
+            .cloned()
+            .filter(|n| always_drives(n.pin_type))
+            .collect();
+        let mut findings = Vec::new();
+        if !inputs.is_empty() && !sourced[index] {
+            let message = format!(
+                "nothing drives net {}, which is connected to {}",
+                net.name,
+                describe(circuit, &inputs)
+            );
+            findings.push((NetCheck::UndrivenInput, inputs[0], message));
+        }
+        if drivers.len() > 1 {
+            let message = format!(
+                "net {} is driven by more than one pin: {}",
+                net.name,
+                describe(circuit, &drivers)
+            );
+            findings.push((NetCheck::MultipleDrivers, drivers[0], message));
+        }
+        if nodes.iter().all(|n| n.pin_type == PinType::Passive) && !sourced[index] {
+            let message = format!(
+                "net {} only connects passive pins, none of which lead to anything driven: {}",
+                net.name,
+                describe(circuit, nodes)
+            );
+            findings.push((NetCheck::FloatingNet, nodes[0], message));
+        }
+
+        for (check, node, message) in findings {
+            let tag = circuit.pin_origin(node).tag;
+            let diagnostic = Diagnostic::new(sources, tag, check.code(), message);
+            match erc.net_check_level(check) {
+                ErcLevel::Ok => {}
+                ErcLevel::Warning => warnings.push(diagnostic.with_severity(Severity::Warning)),
+                ErcLevel::Error => errors.push_diagnostic(tag, diagnostic),
+            }
+        }
+    }
+    errors.into_result()?;
+    Ok(warnings)
+}
+
+/// Finds the nets that have a driver, or a path to one through components with two passive
+/// pins, such as a pull-up resistor
+fn sourced_nets(pins: &[Vec<&Node>]) -> Vec<bool> {
+    let mut passive_nets: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, nodes) in pins.iter().enumerate() {
+        for node in nodes.iter().filter(|n| n.pin_type == PinType::Passive) {
+            passive_nets
+                .entry(&node.reference)
+                .or_insert_with(Vec::new)
+                .push(index);
+        }
+    }
+    passive_nets.retain(|_, nets| nets.len() == 2);
+
+    let mut sourced: Vec<bool> = pins
+        .iter()
+        .map(|nodes| nodes.iter().any(|n| is_driver(n.pin_type)))
+        .collect();
+    let mut pending: Vec<usize> = (0..pins.len()).filter(|&i| sourced[i]).collect();
+    while let Some(index) = pending.pop() {
+        for node in pins[index].iter().filter(|n| n.pin_type == PinType::Passive) {
+            if let Some(nets) = passive_nets.get(node.reference.as_str()) {
+                for &other in nets {
+                    if !sourced[other] {
+                        sourced[other] = true;
+                        pending.push(other);
+                    }
+                }
+            }
+        }
+    }
+    sourced
+}
+
+fn describe(circuit: &Circuit, nodes: &[&Node]) -> String {
+    let pins: Vec<String> = nodes
+        .iter()
+        .map(|node| {
+            format!(
+                "pin {} ({:?}) of {}",
+                node.pin_name,
+                node.pin_type,
+                circuit.pin_origin(node).path
+            )
+        })
+        .collect();
+    pins.join(", ")
+}
@@ -0,0 +1,85 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! The validator only checks pins against each other where they meet in a single component
+//! definition. Pins that meet through abstract pins or `connect` statements only end up on
+//! the same net once the circuit has been instantiated, so they get checked here instead.
+
+use circuit::Circuit;
+use diagnostic::{Diagnostic, Severity};
+use erc::ErcMatrix;
+use error;
+use parse::component::Voltage;
+use parse::source::{Sources, SrcTag};
+use parse::validator::{erc_code, ERCResult};
+
+/// Where a pin of the instantiated circuit came from
+#[derive(Debug, new)]
+pub struct PinOrigin {
+    /// Groups containing the pin's component, then its reference, separated by `/`
+    pub path: String,
+    /// Instantiation of the pin's component
+    pub tag: SrcTag,
+    /// Net that the pin was connected to by name, where the validator has already checked it
+    /// against the other pins connected to the same net, if any
+    pub checked_on: Option<String>,
+    /// Voltage that the pin supplies or accepts, if it states one
+    pub voltage: Option<Voltage>,
+}
+
+/// Checks every pair of pins on each net that the validator couldn't see together, failing
+/// with all of the errors found, or returning the warnings otherwise
+pub fn check(
+    sources: &Sources,
+    circuit: &Circuit,
+    erc: &ErcMatrix,
+) -> error::Result<Vec<Diagnostic>> {
+    let mut errors = error::Errors::new();
+    let mut warnings = Vec::new();
+    for net in &circuit.nets {
+        let nodes = net.distinct_pins();
+        for (index, first) in nodes.iter().enumerate() {
+            for second in &nodes[(index + 1)..] {
+                let first_origin = circuit.pin_origin(first);
+                let second_origin = circuit.pin_origin(second);
+                if first_origin.checked_on.is_some()
+                    && first_origin.checked_on == second_origin.checked_on
+                {
+                    continue;
+                }
+                let severity = match erc.check_connection(first.pin_type, second.pin_type) {
+                    ERCResult::Valid => continue,
+                    ERCResult::Warning => Severity::Warning,
+                    ERCResult::Error => Severity::Error,
+                };
+                let message = format!(
+                    "pin {} ({:?}) of {} is connected by net {} to pin {} ({:?}) of {} at {}",
+                    first.pin_name,
+                    first.pin_type,
+                    first_origin.path,
+                    net.name,
+                    second.pin_name,
+                    second.pin_type,
+                    second_origin.path,
+                    sources.locate(second_origin.tag)
+                );
+                let code = erc_code(first.pin_type, second.pin_type);
+                let diagnostic = Diagnostic::new(sources, first_origin.tag, &code, message)
+                    .with_label(sources, second_origin.tag, "connected to this instance")
+                    .with_severity(severity);
+                match severity {
+                    Severity::Error => errors.push_diagnostic(first_origin.tag, diagnostic),
+                    Severity::Warning => warnings.push(diagnostic),
+                }
+            }
+        }
+    }
+    errors.into_result()?;
+    Ok(warnings)
+}
@@ -8,12 +8,13 @@
 //
 
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::rc::Rc;
 
+use circuit::flat_erc::PinOrigin;
 use circuit::{Circuit, ComponentGroup, ComponentInstance, Net, Node};
 use error;
-use parse::component::{Component, Instance, PinMap, PinType, Unit};
+use parse::component::{Bindings, Component, Instance, PinMap, PinType, Unit};
 use ref_gen::ReferenceGenerator;
 
 type GroupBuilderPtr = Rc<RefCell<GroupBuilder>>;
@@ -40,6 +41,18 @@ impl GroupBuilder {
         self.components.push(reference);
     }
 
+    /// Names of the groups from below the root down to this one, separated by `/`
+    fn path(group: &GroupBuilderPtr) -> String {
+        let group = group.borrow();
+        match group.parent {
+            Some(ref parent) if parent.borrow().parent.is_some() => {
+                format!("{}/{}", GroupBuilder::path(parent), group.name)
+            }
+            Some(_) => group.name.clone(),
+            None => String::new(),
+        }
+    }
+
     fn build(group: GroupBuilderPtr) -> Option<ComponentGroup> {
         let this = Rc::try_unwrap(group).ok().unwrap().into_inner();
         let group = ComponentGroup {
@@ -80,27 +93,25 @@ impl<'a> UnitTracker<'a> {
         Default::default()
     }
 
-    fn add_component(&mut self, reference: &str, component: &'a Component) {
-        assert!(!self.available_units.contains_key(component.name()));
+    fn add_component(&mut self, reference: &str, key: &str, units: &'a [Unit]) {
+        assert!(!self.available_units.contains_key(key));
         self.available_units.insert(
-            component.name().into(),
-            component
-                .units
+            key.into(),
+            units
                 .iter()
                 .map(|c| UnitRef::new(reference.into(), c))
                 .collect(),
         );
     }
 
-    fn next_unit<'b>(&'b mut self, component: &'a Component) -> Option<UnitRef<'a>> {
-        assert!(component.has_units());
-        if !self.available_units.contains_key(component.name()) {
+    fn next_unit<'b>(&'b mut self, key: &str) -> Option<UnitRef<'a>> {
+        if !self.available_units.contains_key(key) {
             None
         } else {
-            let mut units = self.available_units.remove(component.name()).unwrap();
+            let mut units = self.available_units.remove(key).unwrap();
             let unit = units.remove(0);
             if !units.is_empty() {
-                self.available_units.insert(component.name().into(), units);
+                self.available_units.insert(key.into(), units);
             }
             Some(unit)
         }
@@ -109,8 +120,14 @@ impl<'a> UnitTracker<'a> {
 
 struct InstantiationContext<'a> {
     instance: &'a Instance,
+    copy: usize,
     parent_group: GroupBuilderPtr,
     net_map: &'a BTreeMap<String, String>,
+    bindings: &'a Bindings,
+    /// Scope of the parent instantiation that local nets are qualified with
+    scope: &'a str,
+    /// Component whose definition contains the instance, unless it's the top
+    parent: Option<&'a Component>,
 }
 
 impl<'a> InstantiationContext<'a> {
@@ -118,13 +135,36 @@ impl<'a> InstantiationContext<'a> {
         instance: &'a Instance,
         parent_group: GroupBuilderPtr,
         net_map: &'a BTreeMap<String, String>,
+        bindings: &'a Bindings,
+        scope: &'a str,
+        parent: Option<&'a Component>,
     ) -> InstantiationContext<'a> {
         InstantiationContext {
             instance: instance,
+            copy: 0,
             parent_group: parent_group,
             net_map: net_map,
+            bindings: bindings,
+            scope: scope,
+            parent: parent,
         }
     }
+
+    fn with_copy(&self, copy: usize) -> InstantiationContext<'a> {
+        InstantiationContext {
+            instance: self.instance,
+            copy: copy,
+            parent_group: Rc::clone(&self.parent_group),
+            net_map: self.net_map,
+            bindings: self.bindings,
+            scope: self.scope,
+            parent: self.parent,
+        }
+    }
+
+    fn find_connection(&self, pin_name: &str) -> Option<&'a String> {
+        self.instance.find_copy_connection(self.copy, pin_name)
+    }
 }
 
 pub struct Instantiator<'input> {
@@ -134,6 +174,10 @@ pub struct Instantiator<'input> {
     ref_gen: ReferenceGenerator,
     unit_tracker: UnitTracker<'input>,
     aliases: BTreeMap<String, Vec<String>>,
+    /// Names already used to qualify the local nets of abstract component instantiations
+    scopes: HashSet<String>,
+    /// Scope of the top component's instantiation, which named instances inside it leave out
+    top_scope: String,
 }
 
 impl<'input> Instantiator<'input> {
@@ -149,6 +193,8 @@ impl<'input> Instantiator<'input> {
             ref_gen: ReferenceGenerator::new(""),
             unit_tracker: UnitTracker::new(),
             aliases: BTreeMap::new(),
+            scopes: HashSet::new(),
+            top_scope: String::new(),
         }
     }
 
@@ -158,8 +204,16 @@ impl<'input> Instantiator<'input> {
         }
 
         let empty_net_map = BTreeMap::new();
+        let empty_bindings = Bindings::new();
         let root_group = GroupBuilder::new(None, "root".into());
-        let ctx = InstantiationContext::new(instance, root_group, &empty_net_map);
+        let ctx = InstantiationContext::new(
+            instance,
+            root_group,
+            &empty_net_map,
+            &empty_bindings,
+            "",
+            None,
+        );
         self.instantiate_internal(&ctx)?;
         self.circuit.root_group = GroupBuilder::build(ctx.parent_group).unwrap();
         Ok(())
@@ -167,12 +221,15 @@ impl<'input> Instantiator<'input> {
 
     fn instantiate_internal(&mut self, ctx: &InstantiationContext) -> error::Result<()> {
         if let Some(component) = self.components.get(&ctx.instance.name) {
-            if component.is_abstract() {
-                self.instantiate_abstract(ctx, component)?;
-            } else if component.has_units() {
-                self.instantiate_unit(ctx, component)?;
-            } else {
-                self.instantiate_concrete(ctx, component)?;
+            for copy in 0..ctx.instance.copies() {
+                let ctx = ctx.with_copy(copy);
+                if component.is_abstract() {
+                    self.instantiate_abstract(&ctx, component)?;
+                } else if component.has_units() {
+                    self.instantiate_unit(&ctx, component)?;
+                } else {
+                    self.instantiate_concrete(&ctx, component)?;
+                }
             }
             Ok(())
         } else {
@@ -185,10 +242,16 @@ impl<'input> Instantiator<'input> {
         ctx: &InstantiationContext,
         component: &Component,
     ) -> error::Result<()> {
+        let bindings = component.bind(ctx.instance, ctx.bindings);
+        let component = component.expansion(&bindings);
         let mut new_net_map = BTreeMap::new();
-        let anon_ref = self.ref_gen.next(component.name());
+        let (group_name, scope) = self.scope(ctx, component);
+        let named = ctx.instance.copy_label(ctx.copy).is_some();
         for net in &component.nets {
-            let net_name = format!("{}.{}", net, anon_ref);
+            let net_name = format!("{}.{}", net, scope);
+            if named {
+                self.circuit.named_nets.insert(net_name.clone());
+            }
             new_net_map.insert(net.clone(), net_name.clone());
             self.circuit.nets.push(Net::new(net_name));
         }
@@ -196,7 +259,7 @@ impl<'input> Instantiator<'input> {
         for pin in component.abstract_pins() {
             if self.global_nets.contains(&pin.name) {
                 new_net_map.insert(pin.name.clone(), pin.name.clone());
-            } else if let Some(mapped_net) = ctx.instance.find_connection(&pin.name) {
+            } else if let Some(mapped_net) = ctx.find_connection(&pin.name) {
                 if mapped_net == "noconnect" {
                     new_net_map.insert(pin.name.clone(), "noconnect".into());
                 } else if let Some(net_name) = ctx.net_map.get(mapped_net) {
@@ -209,9 +272,16 @@ impl<'input> Instantiator<'input> {
             }
         }
 
-        let group = GroupBuilder::new(Some(Rc::clone(&ctx.parent_group)), anon_ref);
+        let group = GroupBuilder::new(Some(Rc::clone(&ctx.parent_group)), group_name);
         for instance in &component.instances {
-            let child_ctx = InstantiationContext::new(instance, Rc::clone(&group), &new_net_map);
+            let child_ctx = InstantiationContext::new(
+                instance,
+                Rc::clone(&group),
+                &new_net_map,
+                &bindings,
+                &scope,
+                Some(component),
+            );
             self.instantiate_internal(&child_ctx)?;
         }
         GroupBuilder::build(group);
@@ -230,6 +300,41 @@ impl<'input> Instantiator<'input> {
         Ok(())
     }
 
+    /// Returns the group name and the unique name to qualify local nets with for an
+    /// instantiation of an abstract component. Named instances use their label as the group
+    /// name, and the labels of every instance above them up to the top as the scope, such
+    /// as `left/status`, so that it doesn't depend on what else the circuit instantiates.
+    fn scope(&mut self, ctx: &InstantiationContext, component: &Component) -> (String, String) {
+        let (group_name, scope) = match ctx.instance.copy_label(ctx.copy) {
+            Some(label) => {
+                let scope = if ctx.scope == self.top_scope {
+                    label.clone()
+                } else {
+                    format!("{}/{}", ctx.scope, label)
+                };
+                // only an anonymous instance of the top component can have taken it already
+                let scope = if self.scopes.contains(&scope) {
+                    format!("{}/{}", ctx.scope, label)
+                } else {
+                    scope
+                };
+                (label, scope)
+            }
+            None => {
+                let mut anon_ref = self.ref_gen.next(component.short_name());
+                while self.scopes.contains(&anon_ref) {
+                    anon_ref = self.ref_gen.next(component.short_name());
+                }
+                (anon_ref.clone(), anon_ref)
+            }
+        };
+        if ctx.parent.is_none() {
+            self.top_scope = scope.clone();
+        }
+        self.scopes.insert(scope.clone());
+        (group_name, scope)
+    }
+
     fn connect_nets(&mut self, left: &String, right: &String) {
         if !self.aliases.contains_key(right) {
             self.aliases.insert(right.clone(), Vec::new());
@@ -256,7 +361,13 @@ impl<'input> Instantiator<'input> {
         ctx: &InstantiationContext,
         component: &'input Component,
     ) -> error::Result<()> {
-        if let Some(unit_ref) = self.unit_tracker.next_unit(component) {
+        // units of different packages can't share a physical part
+        let (footprint, units) = component.packaged(ctx.instance.package.as_ref());
+        let key = match ctx.instance.package {
+            Some(ref package) => format!("{}:{}", component.name(), package),
+            None => component.name().into(),
+        };
+        if let Some(unit_ref) = self.unit_tracker.next_unit(&key) {
             self.instantiate_pins(ctx, &unit_ref.reference, &unit_ref.unit.pins)?;
         } else {
             let reference = self.ref_gen.next(component.prefix());
@@ -264,15 +375,13 @@ impl<'input> Instantiator<'input> {
 
             self.circuit.instances.push(ComponentInstance::new(
                 reference.clone(),
-                ctx.instance
-                    .value()
-                    .unwrap_or(component.default_value())
-                    .into(),
-                component.footprint().into(),
+                self.instance_value(ctx, component),
+                footprint.into(),
+                self.instance_fields(ctx, component),
             ));
 
-            self.unit_tracker.add_component(&reference, component);
-            let unit_ref = self.unit_tracker.next_unit(component).unwrap();
+            self.unit_tracker.add_component(&reference, &key, units);
+            let unit_ref = self.unit_tracker.next_unit(&key).unwrap();
             self.instantiate_pins(ctx, &reference, &unit_ref.unit.pins)?;
         }
         Ok(())
@@ -288,26 +397,45 @@ impl<'input> Instantiator<'input> {
             if pin.typ == PinType::NoConnect {
                 continue;
             }
-            let node = Node::new(reference.into(), pin.num, pin.name.clone(), pin.typ);
-            if self.global_nets.contains(&pin.name) {
-                self.add_to_net(&pin.name, node)?;
-            } else if let Some(connection_name) = ctx.instance.find_connection(&pin.name) {
-                if connection_name != "noconnect" {
-                    if self.global_nets.contains(connection_name) {
-                        self.add_to_net(connection_name, node)?;
-                    } else if let Some(net_name) = ctx.net_map.get(connection_name) {
-                        if net_name == "noconnect" {
-                            // no connection; no op
-                        } else {
-                            self.add_to_net(net_name, node)?;
-                        }
+            // pins connected to a global net or a net of the parent by name have already been
+            // checked against the other pins connected to it by the validator
+            let mut checked = true;
+            let net_name = if self.global_nets.contains(&pin.name) {
+                Some(&pin.name)
+            } else if let Some(connection_name) = ctx.find_connection(&pin.name) {
+                if connection_name == "noconnect" {
+                    None
+                } else if self.global_nets.contains(connection_name) {
+                    Some(connection_name)
+                } else if let Some(net_name) = ctx.net_map.get(connection_name) {
+                    checked = ctx.parent.map_or(false, |p| p.nets.exists(connection_name));
+                    if net_name == "noconnect" {
+                        None
                     } else {
-                        unreachable!("validation should catch this");
+                        Some(net_name)
                     }
+                } else {
+                    unreachable!("validation should catch this");
                 }
             } else {
                 unreachable!("validation should catch this");
             };
+            if let Some(net_name) = net_name {
+                let origin = PinOrigin::new(
+                    format!("{}/{}", GroupBuilder::path(&ctx.parent_group), reference),
+                    ctx.instance.tag,
+                    if checked { Some(net_name.clone()) } else { None },
+                    pin.voltage,
+                );
+                self.circuit
+                    .pin_origins
+                    .insert((reference.into(), pin.name.clone()), origin);
+                // one node for each of the pin's pads
+                for num in &pin.nums {
+                    let node = Node::new(reference.into(), num.clone(), pin.name.clone(), pin.typ);
+                    self.add_to_net(net_name, node)?;
+                }
+            }
         }
         Ok(())
     }
@@ -320,19 +448,32 @@ impl<'input> Instantiator<'input> {
         let reference = self.ref_gen.next(component.prefix());
         ctx.parent_group.borrow_mut().component(reference.clone());
 
+        let (footprint, units) = component.packaged(ctx.instance.package.as_ref());
         self.circuit.instances.push(ComponentInstance::new(
             reference.clone(),
-            ctx.instance
-                .value()
-                .unwrap_or(component.default_value())
-                .into(),
-            component.footprint().into(),
+            self.instance_value(ctx, component),
+            footprint.into(),
+            self.instance_fields(ctx, component),
         ));
 
-        self.instantiate_pins(ctx, &reference, component.pins())?;
+        self.instantiate_pins(ctx, &reference, &units[0].pins)?;
         Ok(())
     }
 
+    fn instance_value(&self, ctx: &InstantiationContext, component: &Component) -> String {
+        let bindings = component.bind(ctx.instance, ctx.bindings);
+        component.instance_value(ctx.instance, ctx.bindings, &bindings)
+    }
+
+    fn instance_fields(
+        &self,
+        ctx: &InstantiationContext,
+        component: &Component,
+    ) -> Vec<(String, String)> {
+        let bindings = component.bind(ctx.instance, ctx.bindings);
+        component.instance_fields(ctx.instance, ctx.bindings, &bindings)
+    }
+
     fn add_to_net(&mut self, net: &str, node: Node) -> error::Result<()> {
         if let Some(net) = self.circuit.find_net_mut(net) {
             net.nodes.push(node);
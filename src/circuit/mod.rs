@@ -7,17 +7,23 @@
 // copied, modified, or distributed except according to those terms.
 //
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+mod drivers;
+mod flat_erc;
 mod instantiator;
+mod power;
 mod serialize_dot;
 mod serialize_kicad;
 
 pub use circuit::serialize_dot::DotSerializer;
 pub use circuit::serialize_kicad::KicadNetListSerializer;
 
+use circuit::flat_erc::PinOrigin;
 use circuit::instantiator::Instantiator;
+use diagnostic::{self, code, Diagnostic};
 use error;
+use options::Options;
 use parse;
 use parse::component::{Component, Instance, PinNum, PinType};
 use parse::source::Sources;
@@ -27,16 +33,27 @@ pub struct ComponentInstance {
     reference: String,
     value: String,
     footprint: String,
+    fields: Vec<(String, String)>,
 }
 
 impl ComponentInstance {
-    pub fn new(reference: String, value: String, footprint: String) -> ComponentInstance {
+    pub fn new(
+        reference: String,
+        value: String,
+        footprint: String,
+        fields: Vec<(String, String)>,
+    ) -> ComponentInstance {
         ComponentInstance {
             reference: reference,
             value: value,
             footprint: footprint,
+            fields: fields,
         }
     }
+
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
 }
 
 #[derive(Debug)]
@@ -71,6 +88,21 @@ impl Net {
             nodes: Vec::new(),
         }
     }
+
+    /// Nodes of the net with only one for each pin, since pins with several pads have a node
+    /// for each of them
+    pub fn distinct_pins(&self) -> Vec<&Node> {
+        let mut pins: Vec<&Node> = Vec::new();
+        for node in &self.nodes {
+            if !pins
+                .iter()
+                .any(|n| n.reference == node.reference && n.pin_name == node.pin_name)
+            {
+                pins.push(node);
+            }
+        }
+        pins
+    }
 }
 
 #[derive(Default, Debug)]
@@ -94,6 +126,13 @@ pub struct Circuit {
     pub instances: Vec<ComponentInstance>,
     pub nets: Vec<Net>,
     pub root_group: ComponentGroup,
+    /// Problems found while compiling that didn't stop it
+    pub warnings: Vec<Diagnostic>,
+    /// Where each pin came from, by component reference and pin name
+    pin_origins: BTreeMap<(String, String), PinOrigin>,
+    /// Local nets of named instances, which keep their qualified names so that they don't
+    /// change with the rest of the circuit
+    named_nets: BTreeSet<String>,
 }
 
 impl Circuit {
@@ -102,13 +141,33 @@ impl Circuit {
     }
 
     pub fn compile(file_name: &str) -> error::Result<Circuit> {
-        let result = parse::parse(file_name)?;
-        Circuit::from_components(&result.sources, &result.global_nets, result.components)
+        Circuit::compile_with_options(file_name, &Options::default())
+    }
+
+    pub fn compile_with_options(file_name: &str, options: &Options) -> error::Result<Circuit> {
+        let result = parse::parse(file_name, options)?;
+        let mut circuit = Circuit::from_components(
+            &result.sources,
+            &result.global_nets,
+            &result.top,
+            result.components,
+        )?;
+        let mut warnings = flat_erc::check(&result.sources, &circuit, &result.erc)?;
+        warnings.extend(drivers::check(&result.sources, &circuit, &result.erc)?);
+        power::check(&result.sources, &circuit, &result.global_net_voltages)?;
+        circuit.warnings = result.warnings;
+        circuit.warnings.extend(diagnostic::review_warnings(
+            warnings,
+            &result.allows,
+            options.deny_warnings,
+        )?);
+        Ok(circuit)
     }
 
     fn from_components(
         sources: &Sources,
         global_nets: &Vec<String>,
+        top: &str,
         input: Vec<Component>,
     ) -> error::Result<Circuit> {
         let components: BTreeMap<String, Component> = input
@@ -116,25 +175,26 @@ impl Circuit {
             .map(|c| (String::from(c.name()), c))
             .collect();
 
-        let main_component = components.get("Main").unwrap();
+        let top_component = components.get(top).unwrap();
         let mut circuit = Circuit::new();
 
-        let main_instance = Instance::new(main_component.tag, "Main".into());
-        Instantiator::new(&mut circuit, &components, global_nets).instantiate(&main_instance)?;
+        let top_instance = Instance::new(top_component.tag, top.into());
+        Instantiator::new(&mut circuit, &components, global_nets).instantiate(&top_instance)?;
 
         if circuit.instances.is_empty() {
-            err!(
-                "{}: empty circuit: no concrete components",
-                sources.locate(main_instance.tag)
+            err_at!(
+                sources,
+                top_instance.tag,
+                code::EMPTY_CIRCUIT,
+                "empty circuit: no concrete components"
             );
         }
 
         for net in &circuit.nets {
             if net.nodes.len() <= 1 {
-                err!(
-                    "net named {} needs to have more than one connection",
-                    net.name
-                );
+                let message =
+                    format!("net named {} needs to have more than one connection", net.name);
+                return Err(Diagnostic::unlocated(code::CONNECTION, message).into());
             }
         }
 
@@ -143,7 +203,9 @@ impl Circuit {
         for i in 0..len {
             let replacement = {
                 let net_name = &net_names[i];
-                if let Some(dot_index) = net_name.find('.') {
+                if global_nets.contains(net_name) || circuit.named_nets.contains(net_name) {
+                    None
+                } else if let Some(dot_index) = net_name.rfind('.') {
                     let simplified_name = &net_name[0..dot_index];
                     if !net_names
                         .iter()
@@ -170,6 +232,11 @@ impl Circuit {
     pub fn find_net_mut(&mut self, name: &str) -> Option<&mut Net> {
         self.nets.iter_mut().find(|n: &&mut Net| n.name == name)
     }
+
+    fn pin_origin(&self, node: &Node) -> &PinOrigin {
+        let key = (node.reference.clone(), node.pin_name.clone());
+        self.pin_origins.get(&key).unwrap()
+    }
 }
 
 pub trait SerializeCircuit {
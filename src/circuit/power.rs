@@ -0,0 +1,92 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Checks the voltages of power nets. A net gets its voltage from a `global net VCC: 3.3V;`
+//! declaration or from the `power_out` pins that supply it, and every `input` or `power_in`
+//! pin connected to it that states a range, such as `input(lvcmos33)`, has to accept it.
+
+use circuit::Circuit;
+use diagnostic::{code, Diagnostic};
+use error;
+use parse::component::{format_voltage, Voltage};
+use parse::source::{Sources, SrcTag};
+
+/// Checks every net, failing with all of the rails shorted together and the pins given a
+/// voltage they don't accept
+pub fn check(
+    sources: &Sources,
+    circuit: &Circuit,
+    global_net_voltages: &[(String, SrcTag, u32)],
+) -> error::Result<()> {
+    let mut errors = error::Errors::new();
+    for net in &circuit.nets {
+        let pins = net.distinct_pins();
+
+        // each thing that sets the net's voltage, with a description of it
+        let mut rails: Vec<(u32, SrcTag, String)> = Vec::new();
+        for &(ref name, tag, voltage) in global_net_voltages {
+            if *name == net.name {
+                rails.push((voltage, tag, format!("global net {}", name)));
+            }
+        }
+        for node in &pins {
+            let origin = circuit.pin_origin(node);
+            if let Some(Voltage::Supplies(voltage)) = origin.voltage {
+                let description = format!("pin {} of {}", node.pin_name, origin.path);
+                rails.push((voltage, origin.tag, description));
+            }
+        }
+
+        let voltage = match rails.first() {
+            Some(rail) => rail.0,
+            None => continue,
+        };
+        if rails.iter().any(|rail| rail.0 != voltage) {
+            let supplies: Vec<String> = rails
+                .iter()
+                .map(|rail| format!("{} from {}", format_voltage(rail.0), rail.2))
+                .collect();
+            let message = format!(
+                "net {} shorts rails of different voltages together: {}",
+                net.name,
+                supplies.join(", ")
+            );
+            let mut diagnostic = Diagnostic::new(sources, rails[0].1, code::RAIL_SHORT, message);
+            for rail in &rails[1..] {
+                let label = format!("supplies {} here", format_voltage(rail.0));
+                diagnostic = diagnostic.with_label(sources, rail.1, &label);
+            }
+            errors.push_diagnostic(rails[0].1, diagnostic);
+            continue;
+        }
+
+        for node in &pins {
+            let origin = circuit.pin_origin(node);
+            if let Some(Voltage::Accepts(min, max)) = origin.voltage {
+                if voltage < min || voltage > max {
+                    let message = format!(
+                        "pin {} ({:?}) of {} accepts {}, but net {} is at {} from {}",
+                        node.pin_name,
+                        node.pin_type,
+                        origin.path,
+                        Voltage::Accepts(min, max),
+                        net.name,
+                        format_voltage(voltage),
+                        rails[0].2
+                    );
+                    let diagnostic =
+                        Diagnostic::new(sources, origin.tag, code::VOLTAGE_MISMATCH, message)
+                            .with_label(sources, rails[0].1, "net's voltage set here");
+                    errors.push_diagnostic(origin.tag, diagnostic);
+                }
+            }
+        }
+    }
+    errors.into_result()
+}
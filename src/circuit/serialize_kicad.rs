@@ -104,6 +104,18 @@ impl SerializeCircuit for KicadNetListSerializer {
             writeln!(f, "    (comp (ref {})", instance.reference)?;
             writeln!(f, "      (value {})", instance.value)?;
             writeln!(f, "      (footprint {})", instance.footprint)?;
+            if !instance.fields.is_empty() {
+                writeln!(f, "      (fields")?;
+                for &(ref name, ref value) in &instance.fields {
+                    writeln!(
+                        f,
+                        "        (field (name \"{}\") \"{}\")",
+                        escape(name),
+                        escape(value)
+                    )?;
+                }
+                writeln!(f, "      )")?;
+            }
             writeln!(
                 f,
                 "      (sheetpath (names {}/) (tstamps {}/))",
@@ -129,3 +141,7 @@ impl SerializeCircuit for KicadNetListSerializer {
         Ok(f.into_bytes())
     }
 }
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
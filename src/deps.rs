@@ -0,0 +1,183 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! The graph of which components instantiate which. Instantiating a circuit walks down this
+//! graph from the top component, so it has to be free of cycles, or that walk would never
+//! end.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use diagnostic::{code, Diagnostic};
+use error;
+use options::Options;
+use parse;
+use parse::component::Component;
+use parse::source::{Sources, SrcTag};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Visit {
+    New,
+    InProgress,
+    Done,
+}
+
+/// An instance of one component, by index, inside another
+type Link = (usize, usize, SrcTag);
+
+pub struct Dependencies {
+    /// Name of the component to compile, which is the root of the graph
+    top: String,
+    /// Each component's name, with the components it instantiates and where
+    components: Vec<(String, Vec<(usize, SrcTag)>)>,
+}
+
+impl Dependencies {
+    pub fn new(components: &[Component], top: &str) -> Dependencies {
+        let mut indices: BTreeMap<&str, usize> = BTreeMap::new();
+        for (index, component) in components.iter().enumerate() {
+            indices.entry(component.name()).or_insert(index);
+        }
+        let components = components
+            .iter()
+            .map(|component| {
+                let instances = component
+                    .instances
+                    .iter()
+                    .filter_map(|i| indices.get(i.name.as_str()).map(|&c| (c, i.tag)))
+                    .collect();
+                (component.name().to_string(), instances)
+            })
+            .collect();
+        Dependencies {
+            top: top.into(),
+            components: components,
+        }
+    }
+
+    /// The graph of the components needed to compile a file
+    pub fn for_file(file_name: &str, options: &Options) -> error::Result<Dependencies> {
+        let result = parse::parse(file_name, options)?;
+        Ok(Dependencies::new(&result.components, &result.top))
+    }
+
+    /// Fails with every cycle of components that end up instantiating themselves
+    pub fn check_cycles(&self, sources: &Sources) -> error::Result<()> {
+        let mut visits = vec![Visit::New; self.components.len()];
+        let mut chain = Vec::new();
+        let mut errors = error::Errors::new();
+        for index in 0..self.components.len() {
+            self.find_cycles(sources, index, &mut visits, &mut chain, &mut errors);
+        }
+        errors.into_result()
+    }
+
+    /// Searches depth first from a component, where `chain` holds the instances leading to it
+    fn find_cycles(
+        &self,
+        sources: &Sources,
+        index: usize,
+        visits: &mut [Visit],
+        chain: &mut Vec<Link>,
+        errors: &mut error::Errors,
+    ) {
+        match visits[index] {
+            Visit::Done => return,
+            Visit::InProgress => {
+                let start = chain.iter().position(|link| link.0 == index).unwrap();
+                let links = &chain[start..];
+                errors.push_diagnostic(links[0].2, self.describe_cycle(sources, links));
+                return;
+            }
+            Visit::New => {}
+        }
+        visits[index] = Visit::InProgress;
+        for &(child, tag) in &self.components[index].1 {
+            chain.push((index, child, tag));
+            self.find_cycles(sources, child, visits, chain, errors);
+            chain.pop();
+        }
+        visits[index] = Visit::Done;
+    }
+
+    fn describe_cycle(&self, sources: &Sources, links: &[Link]) -> Diagnostic {
+        let steps: Vec<String> = links
+            .iter()
+            .map(|&(parent, child, tag)| {
+                format!(
+                    "{} instantiates {} at {}",
+                    self.name(parent),
+                    self.name(child),
+                    sources.locate(tag)
+                )
+            })
+            .collect();
+        let message = format!(
+            "component {} instantiates itself: {}",
+            self.name(links[0].0),
+            steps.join(", ")
+        );
+        let mut diagnostic = Diagnostic::new(sources, links[0].2, code::CYCLE, message);
+        for &(parent, child, tag) in &links[1..] {
+            let label = format!("{} instantiates {} here", self.name(parent), self.name(child));
+            diagnostic = diagnostic.with_label(sources, tag, &label);
+        }
+        diagnostic
+    }
+
+    fn name(&self, index: usize) -> &str {
+        &self.components[index].0
+    }
+
+    /// Names of the components that the top component instantiates, directly or otherwise,
+    /// including the top component itself
+    pub fn reachable(&self) -> BTreeSet<&str> {
+        self.post_order_from_top().into_iter().map(|index| self.name(index)).collect()
+    }
+
+    /// Indices of the components reachable from the top, each after the ones it depends on
+    fn post_order_from_top(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut visited = vec![false; self.components.len()];
+        if let Some(top) = self.components.iter().position(|c| c.0 == self.top) {
+            self.post_order(top, &mut visited, &mut order);
+        }
+        order
+    }
+
+    /// Writes each component reachable from the top with the components it instantiates,
+    /// listing every component before the ones it depends on
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for &index in self.post_order_from_top().iter().rev() {
+            let mut children: Vec<&str> = Vec::new();
+            for &(child, _) in &self.components[index].1 {
+                if !children.contains(&self.name(child)) {
+                    children.push(self.name(child));
+                }
+            }
+            if children.is_empty() {
+                writeln!(report, "{}", self.name(index)).unwrap();
+            } else {
+                writeln!(report, "{} -> {}", self.name(index), children.join(", ")).unwrap();
+            }
+        }
+        report
+    }
+
+    fn post_order(&self, index: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        visited[index] = true;
+        for &(child, _) in &self.components[index].1 {
+            if !visited[child] {
+                self.post_order(child, visited, order);
+            }
+        }
+        order.push(index);
+    }
+}
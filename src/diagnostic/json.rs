@@ -0,0 +1,52 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use serde_json;
+
+use diagnostic::{chains, Diagnostic, Label, Severity, Span};
+use error::Error;
+
+/// A diagnostic along with the errors that caused it, outermost first
+#[derive(Serialize)]
+struct Message<'a> {
+    severity: Severity,
+    code: &'a str,
+    message: &'a str,
+    span: Option<&'a Span>,
+    labels: &'a [Label],
+    causes: &'a [Diagnostic],
+}
+
+impl<'a> Message<'a> {
+    fn new(diagnostic: &'a Diagnostic, causes: &'a [Diagnostic]) -> Message<'a> {
+        Message {
+            severity: diagnostic.severity,
+            code: &diagnostic.code,
+            message: &diagnostic.message,
+            span: diagnostic.span.as_ref(),
+            labels: &diagnostic.labels,
+            causes: causes,
+        }
+    }
+}
+
+/// Writes each warning and error as a JSON object on its own line
+pub fn format(warnings: &[Diagnostic], error: Option<&Error>) -> String {
+    let chains = error.map(chains).unwrap_or_default();
+    let messages = warnings
+        .iter()
+        .map(|warning| Message::new(warning, &[]))
+        .chain(chains.iter().map(|chain| Message::new(&chain[0], &chain[1..])));
+    let mut out = String::new();
+    for message in messages {
+        out.push_str(&serde_json::to_string(&message).unwrap());
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,397 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Diagnostics are errors that point at the source they were found in. They render with the
+//! offending line and a caret underline, along with labels for other places that explain
+//! the error, such as the original definition of a duplicate:
+//!
+//! ```text
+//! error: instance name led is used more than once in component Main
+//!  --> main.nm:9:5
+//!   |
+//! 9 |     LED led { A: VCC; B: X; }
+//!   |     ^^^^^^^
+//!   |
+//! 8 |     LED led { A: VCC; B: Y; }
+//!   |     ------- first used here
+//! ```
+
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use error::{self, Error, ErrorKind};
+use parse::source::{Sources, SrcTag};
+
+mod json;
+mod sarif;
+
+/// Codes that identify each kind of diagnostic, which stay the same between releases so
+/// that tools can rely on them
+pub mod code {
+    pub const SYNTAX: &'static str = "syntax";
+    pub const MODULE: &'static str = "module";
+    pub const NAMESPACE: &'static str = "namespace";
+    pub const COMPONENT: &'static str = "component";
+    pub const DUPLICATE: &'static str = "duplicate";
+    pub const PIN: &'static str = "pin";
+    pub const BUNDLE: &'static str = "bundle";
+    pub const INSTANCE: &'static str = "instance";
+    pub const PARAMETER: &'static str = "parameter";
+    pub const CONNECTION: &'static str = "connection";
+    pub const TOP: &'static str = "top";
+    pub const CYCLE: &'static str = "cycle";
+    pub const EMPTY_CIRCUIT: &'static str = "empty-circuit";
+    pub const SYSTEM: &'static str = "system";
+    pub const MANIFEST: &'static str = "manifest";
+    pub const UNDRIVEN_INPUT: &'static str = "undriven-input";
+    pub const MULTIPLE_DRIVERS: &'static str = "multiple-drivers";
+    pub const FLOATING_NET: &'static str = "floating-net";
+    pub const RAIL_SHORT: &'static str = "rail-short";
+    pub const VOLTAGE_MISMATCH: &'static str = "voltage-mismatch";
+    pub const UNUSED_COMPONENT: &'static str = "unused-component";
+    pub const UNUSED_NET: &'static str = "unused-net";
+    pub const UNUSED_PIN: &'static str = "unused-pin";
+    pub const REDUNDANT_VALUE: &'static str = "redundant-value";
+    /// Errors that aren't diagnostics, such as failing to read a file
+    pub const ERROR: &'static str = "error";
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A range of a source file, along with the text of the line it starts on
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Span {
+    pub file: String,
+    /// Line and column of the start, counting from 1
+    pub line: usize,
+    pub col: usize,
+    /// Line and column just past the end
+    pub end_line: usize,
+    pub end_col: usize,
+    #[serde(skip)]
+    pub text: String,
+}
+
+impl Span {
+    pub fn new(sources: &Sources, tag: SrcTag) -> Span {
+        let source = sources.source(tag.source);
+        let (line, col) = tag.row_col(&source.code);
+        let (end_line, end_col) = tag.end_row_col(&source.code);
+        Span {
+            file: source.name.clone(),
+            line: line,
+            col: col,
+            end_line: end_line,
+            end_col: end_col,
+            text: source.code.lines().nth(line - 1).unwrap_or("").into(),
+        }
+    }
+
+    /// Columns of the line to underline, which stop at the end of the line for spans that
+    /// continue onto later lines, and cover at least one column
+    fn underline(&self) -> (usize, usize) {
+        let end_col = if self.end_line > self.line {
+            self.text.chars().count() + 1
+        } else {
+            self.end_col
+        };
+        (self.col, end_col.max(self.col + 1))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// One of the `code` constants, or the rule that an ERC result comes from
+    pub code: String,
+    pub message: String,
+    /// Where the diagnostic was found, unless it wasn't found in a source file
+    pub span: Option<Span>,
+    /// Other places in the source that explain the error
+    pub labels: Vec<Label>,
+    /// The source that the span was made from, to keep errors in source order
+    #[serde(skip)]
+    pub tag: Option<SrcTag>,
+}
+
+impl Diagnostic {
+    pub fn new(sources: &Sources, tag: SrcTag, code: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message: message,
+            span: Some(Span::new(sources, tag)),
+            labels: Vec::new(),
+            tag: Some(tag),
+        }
+    }
+
+    pub fn unlocated(code: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: code.into(),
+            message: message,
+            span: None,
+            labels: Vec::new(),
+            tag: None,
+        }
+    }
+
+    pub fn with_label(mut self, sources: &Sources, tag: SrcTag, message: &str) -> Diagnostic {
+        self.labels.push(Label {
+            span: Span::new(sources, tag),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Diagnostic {
+        self.severity = severity;
+        self
+    }
+
+    /// Renders the diagnostic with the source lines it points at, under the given heading
+    pub fn render(&self, heading: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "{}: {}", heading, self.message).unwrap();
+        let span = match self.span {
+            Some(ref span) => span,
+            None => return out,
+        };
+        let gutter = self.labels
+            .iter()
+            .map(|label| label.span.line)
+            .chain(Some(span.line))
+            .max()
+            .unwrap()
+            .to_string()
+            .len();
+        writeln!(out, "{:gutter$}--> {}", "", span, gutter = gutter).unwrap();
+        render_snippet(&mut out, gutter, span, '^', "");
+        for label in &self.labels {
+            if label.span.file != span.file {
+                writeln!(out, "{:gutter$}::: {}", "", label.span, gutter = gutter).unwrap();
+            }
+            render_snippet(&mut out, gutter, &label.span, '-', &label.message);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(ref span) => write!(f, "{}: {}", span, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// A `// netmuncher:allow(rule, ...)` comment, which suppresses the warnings of the rules it
+/// lists that point at its own line or the line after it. A rule is either the code of a
+/// warning or the start of several codes, like `erc`, and ERC rules can name their two pin
+/// types either way around.
+#[derive(Clone, Debug)]
+pub struct Allow {
+    pub span: Span,
+    pub rules: Vec<String>,
+}
+
+impl Allow {
+    pub fn new(sources: &Sources, tag: SrcTag, rules: Vec<String>) -> Allow {
+        Allow {
+            span: Span::new(sources, tag),
+            rules: rules,
+        }
+    }
+
+    pub fn covers(&self, diagnostic: &Diagnostic) -> bool {
+        let near = |span: &Span| {
+            span.file == self.span.file
+                && (span.line == self.span.line || span.line == self.span.line + 1)
+        };
+        diagnostic.span.iter().any(near)
+            && self.rules.iter().any(|rule| rule_matches(rule, &diagnostic.code))
+    }
+}
+
+fn rule_matches(rule: &str, code: &str) -> bool {
+    if code == rule || code.starts_with(&format!("{}-", rule)) {
+        return true;
+    }
+    if let Some(types) = rule.strip_prefix("erc-") {
+        return types
+            .match_indices('-')
+            .any(|(i, _)| format!("erc-{}-{}", &types[(i + 1)..], &types[..i]) == code);
+    }
+    false
+}
+
+/// Drops the warnings that are allowed by comments, and fails with the rest as errors if
+/// warnings are denied
+pub fn review_warnings(
+    warnings: Vec<Diagnostic>,
+    allows: &[Allow],
+    deny: bool,
+) -> error::Result<Vec<Diagnostic>> {
+    let warnings: Vec<Diagnostic> = warnings
+        .into_iter()
+        .filter(|warning| !allows.iter().any(|allow| allow.covers(warning)))
+        .collect();
+    if !deny {
+        return Ok(warnings);
+    }
+    let mut errors = error::Errors::new();
+    for warning in warnings {
+        let tag = warning.tag;
+        errors.push(tag, warning.with_severity(Severity::Error).into());
+    }
+    errors.into_result().map(|_| Vec::new())
+}
+
+impl From<Diagnostic> for Error {
+    fn from(diagnostic: Diagnostic) -> Error {
+        ErrorKind::Diagnostic(diagnostic).into()
+    }
+}
+
+fn render_snippet(out: &mut String, gutter: usize, span: &Span, mark: char, message: &str) {
+    // tabs are expanded so that the underline lines up with the text above it
+    let text = span.text.replace('\t', "    ");
+    let (start, end) = span.underline();
+    let indent: usize = span.text
+        .chars()
+        .take(start - 1)
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let underline: String = (start..end).map(|_| mark).collect();
+
+    writeln!(out, "{:gutter$} |", "", gutter = gutter).unwrap();
+    writeln!(out, "{:>gutter$} | {}", span.line, text, gutter = gutter).unwrap();
+    let marked = format!("{}{} {}", " ".repeat(indent), underline, message);
+    writeln!(out, "{:gutter$} | {}", "", marked.trim_end(), gutter = gutter).unwrap();
+}
+
+/// Renders an error with the source lines it points at, including each error it was caused
+/// by and every error it collects
+pub fn render(error: &Error) -> String {
+    let mut out = String::new();
+    for (index, chain) in chains(error).iter().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        for (depth, diagnostic) in chain.iter().enumerate() {
+            let heading = match depth {
+                0 => diagnostic.severity.to_string(),
+                _ => "caused by".into(),
+            };
+            out.push_str(&diagnostic.render(&heading));
+        }
+    }
+    out
+}
+
+/// Splits an error into each of the errors it collects, as a diagnostic for the error and
+/// each error that caused it, outermost first
+pub fn chains(error: &Error) -> Vec<Vec<Diagnostic>> {
+    match *error.kind() {
+        ErrorKind::MultipleErrors(ref errors) => errors.iter().flat_map(chains).collect(),
+        _ => {
+            let mut chain = Vec::new();
+            let mut next = Some(error);
+            while let Some(error) = next {
+                chain.push(match *error.kind() {
+                    ErrorKind::Diagnostic(ref diagnostic) => diagnostic.clone(),
+                    _ => Diagnostic::unlocated(code::ERROR, error.to_string()),
+                });
+                next = match error.1.next_error {
+                    Some(ref cause) => match cause.downcast_ref::<Error>() {
+                        Some(cause) => Some(cause),
+                        None => {
+                            chain.push(Diagnostic::unlocated(code::ERROR, cause.to_string()));
+                            None
+                        }
+                    },
+                    None => None,
+                };
+            }
+            vec![chain]
+        }
+    }
+}
+
+/// How errors and warnings are written for whoever is reading them
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MessageFormat {
+    /// Rendered with the source lines they point at
+    Human,
+    /// A JSON object on each line
+    Json,
+    /// A single SARIF log, for code scanning tools
+    Sarif,
+}
+
+impl FromStr for MessageFormat {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> error::Result<MessageFormat> {
+        Ok(match s {
+            "human" => MessageFormat::Human,
+            "json" => MessageFormat::Json,
+            "sarif" => MessageFormat::Sarif,
+            _ => {
+                err!("unknown message format {}", s);
+            }
+        })
+    }
+}
+
+impl MessageFormat {
+    /// Formats the warnings found by a run, followed by the error it failed with, if any
+    pub fn format(&self, warnings: &[Diagnostic], error: Option<&Error>) -> String {
+        match *self {
+            MessageFormat::Human => {
+                let mut rendered: Vec<String> = warnings
+                    .iter()
+                    .map(|warning| warning.render(&warning.severity.to_string()))
+                    .collect();
+                rendered.extend(error.map(render));
+                rendered.join("\n")
+            }
+            MessageFormat::Json => json::format(warnings, error),
+            MessageFormat::Sarif => sarif::format(warnings, error),
+        }
+    }
+}
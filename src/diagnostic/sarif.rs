@@ -0,0 +1,173 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Writes diagnostics as a [SARIF 2.1.0](https://sarifweb.azurewebsites.net/) log, which
+//! code scanning tools can show next to the source
+
+use std::collections::BTreeSet;
+
+use serde_json;
+
+use diagnostic::{chains, Diagnostic, Span};
+use error::Error;
+
+const VERSION: &'static str = "2.1.0";
+const SCHEMA: &'static str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Serialize)]
+struct Log {
+    version: &'static str,
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+struct Rule {
+    id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    level: String,
+    message: Message,
+    locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related_locations: Vec<Location>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<Message>,
+}
+
+impl Location {
+    fn new(span: &Span, message: Option<&str>) -> Location {
+        Location {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation {
+                    uri: span.file.replace('\\', "/"),
+                },
+                region: Region {
+                    start_line: span.line,
+                    start_column: span.col,
+                    end_line: span.end_line,
+                    end_column: span.end_col,
+                },
+            },
+            message: message.map(|text| Message { text: text.into() }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+/// Makes a result from a diagnostic and the errors it was caused by. SARIF has no chains of
+/// errors, so the result is reported where the innermost cause points, with the outer
+/// errors as related locations.
+fn to_result(chain: &[Diagnostic]) -> SarifResult {
+    let cause = chain.last().unwrap();
+    let mut related_locations: Vec<Location> = cause
+        .labels
+        .iter()
+        .map(|label| Location::new(&label.span, Some(&label.message)))
+        .collect();
+    for outer in &chain[..chain.len() - 1] {
+        if let Some(ref span) = outer.span {
+            related_locations.push(Location::new(span, Some(&outer.message)));
+        }
+    }
+    let text = chain
+        .iter()
+        .map(|diagnostic| &diagnostic.message[..])
+        .collect::<Vec<&str>>()
+        .join(": ");
+    SarifResult {
+        rule_id: cause.code.clone(),
+        level: chain[0].severity.to_string(),
+        message: Message { text: text },
+        locations: cause.span.iter().map(|span| Location::new(span, None)).collect(),
+        related_locations: related_locations,
+    }
+}
+
+/// Writes every warning and error as a result in a single SARIF log
+pub fn format(warnings: &[Diagnostic], error: Option<&Error>) -> String {
+    let mut results: Vec<SarifResult> = warnings
+        .iter()
+        .map(|warning| to_result(&[warning.clone()]))
+        .collect();
+    if let Some(error) = error {
+        results.extend(chains(error).iter().map(|chain| to_result(chain)));
+    }
+    let rules: BTreeSet<&str> = results.iter().map(|result| &result.rule_id[..]).collect();
+    let driver = Driver {
+        name: "netmuncher",
+        version: env!("CARGO_PKG_VERSION"),
+        rules: rules.into_iter().map(|id| Rule { id: id.into() }).collect(),
+    };
+    let log = Log {
+        version: VERSION,
+        schema: SCHEMA,
+        runs: vec![Run {
+            tool: Tool { driver: driver },
+            results: results,
+        }],
+    };
+    let mut out = serde_json::to_string_pretty(&log).unwrap();
+    out.push('\n');
+    out
+}
@@ -12,7 +12,7 @@ use std::collections::BTreeMap;
 use diagram::data::*;
 use error;
 use parse::ParseResult;
-use parse::component::{Component, Instance, PinType};
+use parse::component::{Bindings, Component, Instance, PinType};
 use ref_gen::ReferenceGenerator;
 
 pub struct DiagramCompiler {
@@ -26,6 +26,7 @@ impl DiagramCompiler {
             input: Input {
                 components: parse_result.components,
                 global_nets: parse_result.global_nets,
+                top: parse_result.top,
             },
             output: Output {
                 ref_gen: ReferenceGenerator::new("_"),
@@ -35,10 +36,14 @@ impl DiagramCompiler {
 
     pub fn compile(mut self) -> error::Result<Diagram> {
         let main = {
-            let main_component = self.input.find_component("Main").unwrap();
-            let main_instance = Instance::new(main_component.tag, "Main".into());
-            self.output
-                .instantiate(&self.input, &main_component, &main_instance)
+            let main_component = self.input.find_component(&self.input.top).unwrap();
+            let main_instance = Instance::new(main_component.tag, self.input.top.clone());
+            self.output.instantiate(
+                &self.input,
+                &main_component,
+                &main_instance,
+                &Bindings::new(),
+            )
         };
 
         Ok(Diagram {
@@ -53,11 +58,21 @@ struct Output {
 }
 
 impl Output {
-    fn instantiate(&mut self, input: &Input, component: &Component, instance: &Instance) -> Node {
+    fn instantiate(
+        &mut self,
+        input: &Input,
+        component: &Component,
+        instance: &Instance,
+        parent_bindings: &Bindings,
+    ) -> Node {
         let instance_name = self.ref_gen.next(&instance.name);
+        let bindings = component.bind(instance, parent_bindings);
+        let component = component.expansion(&bindings);
 
         let mut node = Node::new(instance_name);
-        node.value = instance.value.clone();
+        node.value = instance
+            .value()
+            .map(|value| value.resolve(parent_bindings).to_string());
 
         for pin in &component.units[0].pins {
             use parse::component::PinType::*;
@@ -70,9 +85,13 @@ impl Output {
         }
 
         let mut net_pins: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
-        for instance in &component.instances {
+        for (instance, copy) in component
+            .instances
+            .iter()
+            .flat_map(|i| (0..i.copies()).map(move |copy| (i, copy)))
+        {
             let child_component = input.find_component(&instance.name).unwrap();
-            let child_node = self.instantiate(input, &child_component, &instance);
+            let child_node = self.instantiate(input, &child_component, &instance, &bindings);
             for pin in &child_component.units[0].pins {
                 if input.global_nets.contains(&pin.name) {
                     node.connections.push(Connection {
@@ -85,11 +104,7 @@ impl Output {
                             pin: pin.name.clone(),
                         },
                     });
-                } else if let Some(&(_, ref connection)) = instance
-                    .connections
-                    .iter()
-                    .find(|&&(ref p, _)| *p == pin.name)
-                {
+                } else if let Some(connection) = instance.find_copy_connection(copy, &pin.name) {
                     if input.global_nets.contains(connection) {
                         node.connections.push(Connection {
                             name: pin.name.clone(),
@@ -156,6 +171,7 @@ impl Output {
 struct Input {
     components: Vec<Component>,
     global_nets: Vec<String>,
+    top: String,
 }
 
 impl Input {
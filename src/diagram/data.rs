@@ -11,6 +11,7 @@ use serde_json;
 
 use diagram::compile::DiagramCompiler;
 use error;
+use options::Options;
 use parse;
 
 #[derive(Serialize)]
@@ -21,7 +22,11 @@ pub struct Diagram {
 
 impl Diagram {
     pub fn compile(file_name: &str) -> error::Result<Diagram> {
-        let result = parse::parse(file_name)?;
+        Diagram::compile_with_options(file_name, &Options::default())
+    }
+
+    pub fn compile_with_options(file_name: &str, options: &Options) -> error::Result<Diagram> {
+        let result = parse::parse(file_name, options)?;
         DiagramCompiler::new(result).compile()
     }
 
@@ -0,0 +1,217 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! The electrical rules check decides whether two pin types can be connected from a pair of
+//! tables: one for pins connected by a net, and one for a parent's pin mapped to a child's
+//! pin in an instantiation. Projects can override cells of either table in the `[erc]`
+//! section of the manifest, or with `erc` blocks in their source:
+//!
+//! ```text
+//! erc {
+//!     tristate output = error;
+//!     power_in tristate = ok;
+//!     mapping input tristate = error;
+//! }
+//! ```
+//!
+//! Overrides in the manifest come first, then those in source files, then those in the
+//! compile options, and the last override for a cell wins.
+//!
+//! Once the circuit has been instantiated, each net is also checked as a whole for inputs
+//! that nothing drives, several pins driving it at once, and passive pins that have no path
+//! to anything driven. Those findings are warnings unless a project sets another level for
+//! them, with `undriven-input = "error"` in the manifest or `undriven_input = error;` in an
+//! `erc` block.
+
+use std::cmp;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use diagnostic::code;
+use error;
+use manifest::Manifest;
+use options::{ErcLevel, Options};
+use parse;
+use parse::component::PinType;
+use parse::validator::{check_connection, check_mapping, ERCResult};
+
+const PIN_TYPES: [PinType; 8] = [
+    PinType::Input,
+    PinType::Output,
+    PinType::Passive,
+    PinType::PowerIn,
+    PinType::PowerOut,
+    PinType::Tristate,
+    PinType::Bidirectional,
+    PinType::NoConnect,
+];
+
+const NET_CHECKS: [NetCheck; 3] = [
+    NetCheck::UndrivenInput,
+    NetCheck::MultipleDrivers,
+    NetCheck::FloatingNet,
+];
+
+/// Checks of all of the pins on a net together, rather than in pairs
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NetCheck {
+    /// Input pins on a net that nothing drives
+    UndrivenInput,
+    /// More than one pin driving a net that can't stop driving it
+    MultipleDrivers,
+    /// Nets of passive pins with no path to anything driven
+    FloatingNet,
+}
+
+impl NetCheck {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            NetCheck::UndrivenInput => code::UNDRIVEN_INPUT,
+            NetCheck::MultipleDrivers => code::MULTIPLE_DRIVERS,
+            NetCheck::FloatingNet => code::FLOATING_NET,
+        }
+    }
+}
+
+impl FromStr for NetCheck {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> error::Result<NetCheck> {
+        match NET_CHECKS.iter().find(|check| check.code() == s) {
+            Some(check) => Ok(*check),
+            None => {
+                err!("unknown net check {}", s);
+            }
+        }
+    }
+}
+
+/// The ERC tables along with a project's overrides of them
+#[derive(Clone, Debug, Default)]
+pub struct ErcMatrix {
+    /// Overrides for two pins connected by a net, which apply either way around
+    pub connections: Vec<(PinType, PinType, ErcLevel)>,
+    /// Overrides for a parent's pin, first, mapped to a child's pin, second
+    pub mappings: Vec<(PinType, PinType, ErcLevel)>,
+    /// Levels of the checks of whole nets
+    pub net_checks: Vec<(NetCheck, ErcLevel)>,
+}
+
+impl ErcMatrix {
+    pub fn new() -> ErcMatrix {
+        Default::default()
+    }
+
+    /// The matrix used to compile a file, including the `erc` blocks of every module it
+    /// requires
+    pub fn for_file(file_name: &str, options: &Options) -> error::Result<ErcMatrix> {
+        Ok(parse::parse(file_name, options)?.erc)
+    }
+
+    /// The matrix given by a project manifest and compile options, without any source
+    pub fn for_project(
+        manifest: Option<&Manifest>,
+        options: &Options,
+    ) -> error::Result<ErcMatrix> {
+        let mut matrix = match manifest {
+            Some(manifest) => ErcMatrix::from_manifest(manifest)?,
+            None => ErcMatrix::new(),
+        };
+        matrix.extend(ErcMatrix::from_options(options));
+        Ok(matrix)
+    }
+
+    pub fn from_manifest(manifest: &Manifest) -> error::Result<ErcMatrix> {
+        Ok(ErcMatrix {
+            connections: manifest.erc_overrides()?,
+            mappings: manifest.erc_mapping_overrides()?,
+            net_checks: manifest.net_check_levels(),
+        })
+    }
+
+    pub fn from_options(options: &Options) -> ErcMatrix {
+        ErcMatrix {
+            connections: options.erc_overrides.clone(),
+            mappings: options.erc_mapping_overrides.clone(),
+            net_checks: options.net_check_levels.clone(),
+        }
+    }
+
+    /// Adds overrides that take precedence over the ones already present
+    pub fn extend(&mut self, other: ErcMatrix) {
+        self.connections.extend(other.connections);
+        self.mappings.extend(other.mappings);
+        self.net_checks.extend(other.net_checks);
+    }
+
+    pub fn check_connection(&self, first: PinType, second: PinType) -> ERCResult {
+        check_connection(&self.connections, first, second)
+    }
+
+    pub fn check_mapping(&self, parent: PinType, child: PinType) -> ERCResult {
+        check_mapping(&self.mappings, parent, child)
+    }
+
+    pub fn net_check_level(&self, check: NetCheck) -> ErcLevel {
+        self.net_checks
+            .iter()
+            .rev()
+            .find(|c| c.0 == check)
+            .map_or(ErcLevel::Warning, |c| c.1)
+    }
+
+    /// Writes both tables and the levels of the net checks, with every override applied
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        writeln!(report, "Pins connected by a net:").unwrap();
+        write_table(&mut report, |first, second| self.check_connection(first, second));
+        writeln!(report, "\nParent pins (rows) mapped to child pins (columns):").unwrap();
+        write_table(&mut report, |parent, child| self.check_mapping(parent, child));
+        writeln!(report, "\nWhole nets:").unwrap();
+        let width = NET_CHECKS.iter().map(|c| c.code().len()).max().unwrap();
+        for check in &NET_CHECKS {
+            let level = match self.net_check_level(*check) {
+                ErcLevel::Ok => "ok",
+                ErcLevel::Warning => "warning",
+                ErcLevel::Error => "error",
+            };
+            writeln!(report, "{:width$}  {}", check.code(), level, width = width).unwrap();
+        }
+        report
+    }
+}
+
+/// Writes a table with a row and a column for each pin type
+fn write_table<F>(report: &mut String, check: F)
+where
+    F: Fn(PinType, PinType) -> ERCResult,
+{
+    let width = PIN_TYPES.iter().map(|t| t.keyword().len()).max().unwrap();
+    let mut header = format!("{:width$}", "", width = width);
+    for column in &PIN_TYPES {
+        write!(header, "  {:width$}", column.keyword(), width = column_width(*column)).unwrap();
+    }
+    writeln!(report, "{}", header.trim_end()).unwrap();
+    for row in &PIN_TYPES {
+        let mut line = format!("{:width$}", row.keyword(), width = width);
+        for column in &PIN_TYPES {
+            let cell = match check(*row, *column) {
+                ERCResult::Valid => "ok",
+                ERCResult::Warning => "warning",
+                ERCResult::Error => "error",
+            };
+            write!(line, "  {:width$}", cell, width = column_width(*column)).unwrap();
+        }
+        writeln!(report, "{}", line.trim_end()).unwrap();
+    }
+}
+
+fn column_width(pin_type: PinType) -> usize {
+    cmp::max(pin_type.keyword().len(), "warning".len())
+}
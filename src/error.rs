@@ -7,6 +7,11 @@
 // copied, modified, or distributed except according to those terms.
 //
 
+use std::collections::HashSet;
+
+use diagnostic::Diagnostic;
+use parse::source::SrcTag;
+
 macro_rules! err {
     ($msg:expr) => {
         return Err(error::ErrorKind::NetmuncherError($msg.into()).into());
@@ -16,6 +21,34 @@ macro_rules! err {
     };
 }
 
+/// Like `err!`, but for an error found at a place in the source, which is shown along with it,
+/// and with one of the `diagnostic::code` constants
+macro_rules! err_at {
+    ($sources:expr, $tag:expr, $code:expr, $msg:expr $(, $prm:expr)*) => {
+        return Err(error::ErrorKind::Diagnostic(::diagnostic::Diagnostic::new(
+            $sources,
+            $tag,
+            $code,
+            format!($msg, $($prm,)*),
+        )).into());
+    };
+}
+
+/// Like `err_at!`, but adds the error to an `Errors` list instead of returning
+macro_rules! report {
+    ($errors:expr, $sources:expr, $tag:expr, $code:expr, $msg:expr $(, $prm:expr)*) => {
+        $errors.push(
+            Some($tag),
+            error::ErrorKind::Diagnostic(::diagnostic::Diagnostic::new(
+                $sources,
+                $tag,
+                $code,
+                format!($msg, $($prm,)*),
+            )).into(),
+        );
+    };
+}
+
 error_chain! {
     types {
         Error, ErrorKind, ResultExt, Result;
@@ -35,5 +68,105 @@ error_chain! {
             description("netmuncher error")
             display("{}", msg)
         }
+        /// An error at a place in the source
+        Diagnostic(diagnostic: ::diagnostic::Diagnostic) {
+            description("netmuncher error")
+            display("{}", diagnostic)
+        }
+        /// Several errors found in one run, each with its causes
+        MultipleErrors(errors: Vec<Error>) {
+            description("multiple netmuncher errors")
+            display("{}", join_errors(errors))
+        }
+    }
+}
+
+/// Errors collected by checks that keep going after finding one, so that a single run can
+/// report all of them
+#[derive(Debug, Default)]
+pub struct Errors {
+    /// Each error along with where it was found, if it was found in a source file
+    errors: Vec<(Option<SrcTag>, Error)>,
+    /// Where each error was found along with its description, to skip ones found again
+    found: HashSet<(Option<SrcTag>, String)>,
+}
+
+impl Errors {
+    pub fn new() -> Errors {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
     }
+
+    /// Adds an error, unless the same one has already been found
+    pub fn push(&mut self, tag: Option<SrcTag>, error: Error) {
+        if self.found.insert((tag, describe(&error))) {
+            self.errors.push((tag, error));
+        }
+    }
+
+    /// Adds the error of a failed result, or returns the value of a successful one
+    pub fn check<T>(&mut self, tag: Option<SrcTag>, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.push(tag, error);
+                None
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: Errors) {
+        for (tag, error) in other.errors {
+            self.push(tag, error);
+        }
+    }
+
+    /// Chains the same error onto each error found, such as the component they were found in
+    pub fn chain_err<F>(self, make: F) -> Errors
+    where
+        F: Fn() -> ErrorKind,
+    {
+        let mut chained = Errors::new();
+        for (tag, error) in self.errors {
+            chained.push(tag, error.chain_err(&make));
+        }
+        chained
+    }
+
+    /// Fails with every error found, sorted by where they were found in the source, and
+    /// followed by those that weren't found in a source file
+    pub fn into_result(mut self) -> Result<()> {
+        self.errors.sort_by_key(|&(tag, _)| match tag {
+            Some(tag) => (0, tag.source, tag.offset),
+            None => (1, 0, 0),
+        });
+        if self.errors.len() <= 1 {
+            return match self.errors.pop() {
+                Some((_, error)) => Err(error),
+                None => Ok(()),
+            };
+        }
+        let errors = self.errors.into_iter().map(|(_, error)| error).collect();
+        Err(ErrorKind::MultipleErrors(errors).into())
+    }
+
+    /// Adds a diagnostic, sorted by where its main span points
+    pub fn push_diagnostic(&mut self, tag: SrcTag, diagnostic: Diagnostic) {
+        self.push(Some(tag), ErrorKind::Diagnostic(diagnostic).into());
+    }
+}
+
+fn join_errors(errors: &[Error]) -> String {
+    let messages: Vec<String> = errors.iter().map(describe).collect();
+    messages.join("\nError: ")
+}
+
+/// An error's message followed by its causes, like `display_chain` but without the
+/// backtrace that it adds when `RUST_BACKTRACE` is set
+fn describe(error: &Error) -> String {
+    let messages: Vec<String> = error.iter().map(|e| e.to_string()).collect();
+    messages.join("\nCaused by: ")
 }
@@ -21,6 +21,7 @@ extern crate serde_derive;
 
 extern crate serde;
 extern crate serde_json;
+extern crate toml;
 
 #[macro_use]
 pub mod error;
@@ -28,4 +29,11 @@ pub mod error;
 mod parse;
 mod ref_gen;
 pub mod circuit;
+pub mod deps;
+pub mod diagnostic;
 pub mod diagram;
+pub mod erc;
+pub mod lint;
+pub mod manifest;
+pub mod options;
+pub mod system;
@@ -0,0 +1,245 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Lints find definitions that don't affect the circuit, which tend to pile up in large
+//! libraries. Each has a stable name that sets its level in the `[lints]` section of the
+//! manifest, with `--lint` on the command line, or that a `netmuncher:allow` comment can
+//! name:
+//!
+//! ```toml
+//! [lints]
+//! unused-component = "warn"
+//! unused-net = "deny"
+//! ```
+//!
+//! Every lint warns by default, apart from `unused-component`, since a library usually
+//! defines components for more projects than the one being compiled.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use deps::Dependencies;
+use diagnostic::{code, Diagnostic, Severity};
+use error;
+use parse::component::{Component, PinType, ValueExpr};
+use parse::source::{Sources, SrcTag};
+
+const LINTS: [Lint; 4] = [
+    Lint::UnusedComponent,
+    Lint::UnusedNet,
+    Lint::UnusedPin,
+    Lint::RedundantValue,
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Lint {
+    /// Components that the top component never instantiates, directly or otherwise
+    UnusedComponent,
+    /// Nets that no instance or `connect` uses
+    UnusedNet,
+    /// Pins of abstract components that no instance or `connect` inside them uses, other than
+    /// `noconnect` pins
+    UnusedPin,
+    /// Instances that set their value to the component's default
+    RedundantValue,
+}
+
+impl Lint {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Lint::UnusedComponent => code::UNUSED_COMPONENT,
+            Lint::UnusedNet => code::UNUSED_NET,
+            Lint::UnusedPin => code::UNUSED_PIN,
+            Lint::RedundantValue => code::REDUNDANT_VALUE,
+        }
+    }
+
+    fn default_level(&self) -> LintLevel {
+        match *self {
+            Lint::UnusedComponent => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl FromStr for Lint {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> error::Result<Lint> {
+        match LINTS.iter().find(|lint| lint.code() == s) {
+            Some(lint) => Ok(*lint),
+            None => {
+                err!("unknown lint {}", s);
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl FromStr for LintLevel {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> error::Result<LintLevel> {
+        Ok(match s {
+            "allow" => LintLevel::Allow,
+            "warn" => LintLevel::Warn,
+            "deny" => LintLevel::Deny,
+            _ => {
+                err!("unknown lint level {}, which should be allow, warn or deny", s);
+            }
+        })
+    }
+}
+
+/// Reads a lint's level from the command line, written like `unused-net=deny`
+pub fn parse_setting(setting: &str) -> error::Result<(Lint, LintLevel)> {
+    let mut parts = setting.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(lint), Some(level)) => Ok((lint.parse()?, level.parse()?)),
+        _ => {
+            err!("expected a lint and a level like unused-net=deny, found {}", setting);
+        }
+    }
+}
+
+/// Runs every lint that isn't allowed, failing with the findings of those that are denied,
+/// or returning the warnings otherwise
+pub fn check(
+    sources: &Sources,
+    components: &[Component],
+    global_nets: &[String],
+    top: &str,
+    levels: &[(Lint, LintLevel)],
+) -> error::Result<Vec<Diagnostic>> {
+    let level = |lint: Lint| {
+        levels
+            .iter()
+            .rev()
+            .find(|l| l.0 == lint)
+            .map_or(lint.default_level(), |l| l.1)
+    };
+
+    let mut findings: Vec<(Lint, SrcTag, String)> = Vec::new();
+    if level(Lint::UnusedComponent) != LintLevel::Allow {
+        let used = used_components(components, top);
+        for component in components {
+            if !used.contains(component.name()) {
+                let message = format!(
+                    "component {} is never used by {}",
+                    component.name(),
+                    top
+                );
+                findings.push((Lint::UnusedComponent, component.tag, message));
+            }
+        }
+    }
+    for component in components.iter().filter(|c| c.is_abstract()) {
+        let used = used_names(component);
+        let branch_nets = component.branches.iter().map(|b| &b.nets);
+        for nets in ::std::iter::once(&component.nets).chain(branch_nets) {
+            for net in nets {
+                if !used.contains(net.as_str()) {
+                    let message = format!(
+                        "net {} of component {} isn't connected to anything",
+                        net,
+                        component.name()
+                    );
+                    findings.push((Lint::UnusedNet, nets.tag(net).unwrap(), message));
+                }
+            }
+        }
+        for pin in component.abstract_pins() {
+            // pins named after a global net connect to it without being mapped
+            let implicit = global_nets.contains(&pin.name);
+            if pin.typ != PinType::NoConnect && !implicit && !used.contains(pin.name.as_str()) {
+                let message = format!(
+                    "pin {} of component {} isn't connected to anything inside it",
+                    pin.name,
+                    component.name()
+                );
+                findings.push((Lint::UnusedPin, pin.tag, message));
+            }
+        }
+        for instance in &component.instances {
+            let value = match instance.value {
+                Some(ValueExpr::Literal(ref value)) => value,
+                _ => continue,
+            };
+            let child = match components.iter().find(|c| c.name() == instance.name) {
+                Some(child) => child,
+                None => continue,
+            };
+            if child.default_value() == ValueExpr::Literal(value.clone()) {
+                let message = format!(
+                    "value {} is the same as the default value of {}",
+                    value, instance.name
+                );
+                findings.push((Lint::RedundantValue, instance.tag, message));
+            }
+        }
+    }
+
+    let mut errors = error::Errors::new();
+    let mut warnings = Vec::new();
+    for (lint, tag, message) in findings {
+        let diagnostic = Diagnostic::new(sources, tag, lint.code(), message);
+        match level(lint) {
+            LintLevel::Allow => {}
+            LintLevel::Warn => warnings.push(diagnostic.with_severity(Severity::Warning)),
+            LintLevel::Deny => errors.push_diagnostic(tag, diagnostic),
+        }
+    }
+    errors.into_result()?;
+    Ok(warnings)
+}
+
+/// Names of the components that the top component needs, along with the components those
+/// extend
+fn used_components<'a>(components: &'a [Component], top: &str) -> BTreeSet<&'a str> {
+    let deps = Dependencies::new(components, top);
+    let reachable = deps.reachable();
+    let mut used = BTreeSet::new();
+    for component in components {
+        if reachable.contains(component.name()) {
+            let mut ancestor = Some(component);
+            while let Some(component) = ancestor {
+                used.insert(component.name());
+                ancestor = component
+                    .inherited_from
+                    .as_ref()
+                    .and_then(|parent| components.iter().find(|c| c.name() == parent));
+            }
+        }
+    }
+    used
+}
+
+/// Names of the pins and nets that a component's instances and `connect` statements use
+fn used_names(component: &Component) -> BTreeSet<&str> {
+    let mut used = BTreeSet::new();
+    for instance in &component.instances {
+        used.extend(instance.connections.iter().map(|c| c.1.as_str()));
+        for &(_, ref nets) in &instance.spread_connections {
+            used.extend(nets.iter().map(String::as_str));
+        }
+    }
+    let branch_connects = component.branches.iter().flat_map(|b| &b.connects);
+    for &(ref left, ref right) in component.connects.iter().chain(branch_connects) {
+        used.insert(left.as_str());
+        used.insert(right.as_str());
+    }
+    used
+}
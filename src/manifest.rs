@@ -0,0 +1,391 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! The project manifest is a `netmuncher.toml` file in the directory of the circuit being
+//! compiled, or in one of its parents. It names the project's entry point and the outputs
+//! to generate from it with `netmuncher build`, along with settings that apply to every
+//! compile in the project, such as library paths and ERC overrides:
+//!
+//! ```toml
+//! entry = "main.nm"
+//! lib-paths = ["../libs"]
+//!
+//! [erc]
+//! output-tristate = "error"
+//! mapping-input-tristate = "error"
+//! undriven-input = "error"
+//!
+//! [lints]
+//! unused-net = "deny"
+//!
+//! [[output]]
+//! format = "kicad"
+//! path = "build/main.net"
+//! ```
+//!
+//! Projects made of several boards list them along with the links between them instead,
+//! as described in the `system` module.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use erc::NetCheck;
+use diagnostic::{code, Diagnostic};
+use error;
+use lint::{Lint, LintLevel};
+use options::ErcLevel;
+use parse::component::PinType;
+use parse::source::{Sources, SrcTag};
+
+pub const MANIFEST_NAME: &str = "netmuncher.toml";
+
+/// Start of the ERC rules that apply to a parent's pin mapped to a child's pin
+const MAPPING_PREFIX: &str = "mapping-";
+
+/// A file to generate with `netmuncher build`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// One of `kicad`, `dot` or `diagram_json`
+    pub format: String,
+    pub path: PathBuf,
+}
+
+/// One board of a multi-board system
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Board {
+    pub name: String,
+    /// Component to compile for the board
+    pub top: String,
+    /// Source file that defines the board, instead of the manifest's entry point
+    pub entry: Option<PathBuf>,
+    /// KiCad netlist to generate for the board
+    pub output: Option<PathBuf>,
+}
+
+/// A cable or pair of mating connectors that joins connectors on two boards
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Link {
+    /// Connector at one end, as a board name and reference like `backplane.J1`
+    pub from: String,
+    pub to: String,
+    /// Pin of `to` joined to each pin of `from`, for cables that aren't wired straight through
+    #[serde(default)]
+    pub pins: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Manifest {
+    /// Directory the manifest is in, which relative paths in it are relative to
+    #[serde(skip)]
+    pub dir: PathBuf,
+    /// Text of the manifest, which diagnostics about its entries point into
+    #[serde(skip)]
+    pub text: String,
+    /// Source file to compile
+    pub entry: Option<PathBuf>,
+    /// Component to compile from the entry point
+    pub top: Option<String>,
+    /// Directories to search for required modules
+    #[serde(default)]
+    pub lib_paths: Vec<PathBuf>,
+    /// ERC results for connections between two pin types, keyed like `output-tristate`
+    #[serde(default)]
+    pub erc: BTreeMap<String, ErcLevel>,
+    /// Levels of lints, keyed by their names like `unused-net`
+    #[serde(default)]
+    pub lints: BTreeMap<String, LintLevel>,
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<Output>,
+    #[serde(default, rename = "board")]
+    pub boards: Vec<Board>,
+    #[serde(default, rename = "link")]
+    pub links: Vec<Link>,
+    /// Report of the signals carried by each link, for multi-board systems
+    pub wiring_report: Option<PathBuf>,
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> error::Result<Manifest> {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+        let mut manifest: Manifest = match toml::from_str(&contents) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                let mut sources = Sources::new();
+                sources.push_source(path.to_string_lossy().into_owned(), contents.clone());
+                let offset = err.line_col().map_or(0, |(line, col)| {
+                    contents.split('\n').take(line).map(|l| l.len() + 1).sum::<usize>() + col
+                });
+                // the location is already given by the diagnostic
+                let message = err.to_string();
+                let message = match message.rfind(" at line ") {
+                    Some(index) if err.line_col().is_some() => message[..index].to_string(),
+                    _ => message,
+                };
+                err_at!(&sources, SrcTag::new(0, offset), code::MANIFEST, "{}", message);
+            }
+        };
+        manifest.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        manifest.text = contents;
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    /// Checks the settings that the manifest's types don't, failing with all of the errors
+    /// found
+    fn validate(&self) -> error::Result<()> {
+        let sources = self.sources();
+        let mut errors = error::Errors::new();
+        for (index, output) in self.outputs.iter().enumerate() {
+            match output.format.as_str() {
+                "kicad" | "dot" | "diagram_json" => {}
+                _ => {
+                    report!(
+                        errors,
+                        &sources,
+                        self.entry_tag("output", index),
+                        code::MANIFEST,
+                        "unknown output format {} for {}",
+                        output.format,
+                        output.path.display()
+                    );
+                }
+            }
+        }
+        for (index, board) in self.boards.iter().enumerate() {
+            if let Some(first) = self.boards[0..index].iter().position(|b| b.name == board.name) {
+                let tag = self.entry_tag("board", index);
+                let message = format!("duplicate board named {}", board.name);
+                let diagnostic = Diagnostic::new(&sources, tag, code::DUPLICATE, message)
+                    .with_label(&sources, self.entry_tag("board", first), "first defined here");
+                errors.push_diagnostic(tag, diagnostic);
+            }
+        }
+        for rule in self.erc.keys() {
+            if let Err(err) = erc_rule(rule) {
+                let tag = self.key_tag("erc", rule);
+                report!(errors, &sources, tag, code::MANIFEST, "{}", err);
+            }
+        }
+        for lint in self.lints.keys() {
+            if let Err(err) = lint.parse::<Lint>() {
+                let tag = self.key_tag("lints", lint);
+                report!(errors, &sources, tag, code::MANIFEST, "{}", err);
+            }
+        }
+        errors.into_result()
+    }
+
+    /// Finds the manifest for a project by searching the given directory and the parents
+    /// named in its path
+    pub fn find<P: AsRef<Path>>(dir: P) -> error::Result<Option<Manifest>> {
+        for candidate in dir.as_ref().ancestors() {
+            let path = candidate.join(MANIFEST_NAME);
+            if path.is_file() {
+                return Ok(Some(Manifest::load(path)?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The manifest as the only source of a set of sources, for diagnostics about its entries
+    pub fn sources(&self) -> Sources {
+        let mut sources = Sources::new();
+        let name = self.dir.join(MANIFEST_NAME).to_string_lossy().into_owned();
+        sources.push_source(name, self.text.clone());
+        sources
+    }
+
+    /// Location of the header of an entry in an array of tables, like the second `[[link]]`,
+    /// or the start of the manifest if it can't be found
+    pub fn entry_tag(&self, table: &str, index: usize) -> SrcTag {
+        let lines = self.lines();
+        let mut headers = lines
+            .iter()
+            .filter(|&&(_, line)| table_header(line) == Some((true, table)));
+        match headers.nth(index) {
+            Some(&(offset, line)) => line_tag(offset, line),
+            None => SrcTag::new(0, 0),
+        }
+    }
+
+    /// Location of a key in a table, like `unused-net` in `[lints]`, or of the table's header
+    /// if the key can't be found
+    fn key_tag(&self, table: &str, key: &str) -> SrcTag {
+        let mut tag = SrcTag::new(0, 0);
+        let mut in_table = false;
+        for (offset, line) in self.lines() {
+            if let Some(header) = table_header(line) {
+                in_table = header == (false, table);
+                if in_table {
+                    tag = line_tag(offset, line);
+                }
+            } else if in_table {
+                let name = line.split('=').next().unwrap().trim().trim_matches('"');
+                if name == key {
+                    let start = offset + line.find(|c: char| !c.is_whitespace()).unwrap();
+                    return SrcTag::span(0, start, start + line.trim().len());
+                }
+            }
+        }
+        tag
+    }
+
+    /// Each line of the manifest, along with the offset of its start
+    fn lines(&self) -> Vec<(usize, &str)> {
+        let mut offset = 0;
+        let mut lines = Vec::new();
+        for line in self.text.split('\n') {
+            lines.push((offset, line));
+            offset += line.len() + 1;
+        }
+        lines
+    }
+
+    /// Path of a board's source file, relative to the current directory
+    pub fn board_entry_path(&self, board: &Board) -> error::Result<PathBuf> {
+        match board.entry {
+            Some(ref entry) => Ok(self.dir.join(entry)),
+            None => self.entry_path(),
+        }
+    }
+
+    /// Path of the entry point, relative to the current directory rather than the manifest
+    pub fn entry_path(&self) -> error::Result<PathBuf> {
+        match self.entry {
+            Some(ref entry) => Ok(self.dir.join(entry)),
+            None => {
+                let tag = SrcTag::new(0, 0);
+                let message = "the manifest doesn't name an entry point".into();
+                Err(Diagnostic::new(&self.sources(), tag, code::MANIFEST, message).into())
+            }
+        }
+    }
+
+    /// Library paths, relative to the current directory rather than the manifest
+    pub fn lib_paths(&self) -> Vec<PathBuf> {
+        self.lib_paths.iter().map(|path| self.dir.join(path)).collect()
+    }
+
+    /// Output paths, relative to the current directory rather than the manifest
+    pub fn outputs(&self) -> Vec<Output> {
+        self.outputs
+            .iter()
+            .map(|output| Output {
+                format: output.format.clone(),
+                path: self.dir.join(&output.path),
+            })
+            .collect()
+    }
+
+    /// Netlist path of each board that has one, relative to the current directory
+    pub fn board_outputs(&self) -> Vec<(String, PathBuf)> {
+        self.boards
+            .iter()
+            .filter_map(|board| {
+                board
+                    .output
+                    .as_ref()
+                    .map(|output| (board.name.clone(), self.dir.join(output)))
+            })
+            .collect()
+    }
+
+    /// Path of the wiring report, relative to the current directory
+    pub fn wiring_report_path(&self) -> Option<PathBuf> {
+        self.wiring_report.as_ref().map(|path| self.dir.join(path))
+    }
+
+    /// ERC results for pins connected by a net, from rules like `output-tristate`
+    pub fn erc_overrides(&self) -> error::Result<Vec<(PinType, PinType, ErcLevel)>> {
+        self.erc_rules(false)
+    }
+
+    /// ERC results for a parent's pin mapped to a child's pin, from rules like
+    /// `mapping-input-tristate`
+    pub fn erc_mapping_overrides(&self) -> error::Result<Vec<(PinType, PinType, ErcLevel)>> {
+        self.erc_rules(true)
+    }
+
+    /// Levels of the checks of whole nets, from rules like `undriven-input`
+    pub fn net_check_levels(&self) -> Vec<(NetCheck, ErcLevel)> {
+        self.erc
+            .iter()
+            .filter_map(|(rule, &level)| rule.parse().ok().map(|check| (check, level)))
+            .collect()
+    }
+
+    pub fn lint_levels(&self) -> error::Result<Vec<(Lint, LintLevel)>> {
+        let mut levels = Vec::new();
+        for (lint, &level) in &self.lints {
+            levels.push((lint.parse()?, level));
+        }
+        Ok(levels)
+    }
+
+    fn erc_rules(&self, mapping: bool) -> error::Result<Vec<(PinType, PinType, ErcLevel)>> {
+        let mut overrides = Vec::new();
+        for (rule, &level) in &self.erc {
+            if let Some((is_mapping, first, second)) = erc_rule(rule)? {
+                if is_mapping == mapping {
+                    overrides.push((first, second, level));
+                }
+            }
+        }
+        Ok(overrides)
+    }
+}
+
+/// Pin types of an ERC rule, and whether it's for a mapping, or none for a net check
+fn erc_rule(rule: &str) -> error::Result<Option<(bool, PinType, PinType)>> {
+    if rule.parse::<NetCheck>().is_ok() {
+        return Ok(None);
+    }
+    let (is_mapping, pin_types) = match rule.strip_prefix(MAPPING_PREFIX) {
+        Some(pin_types) => (true, pin_types),
+        None => (false, rule),
+    };
+    let pin_types: Vec<Option<PinType>> = pin_types.split('-').map(|t| t.parse().ok()).collect();
+    match pin_types.as_slice() {
+        &[Some(first), Some(second)] => Ok(Some((is_mapping, first, second))),
+        _ => {
+            err!(
+                "unknown ERC rule {}, which should name two pin types like output-tristate",
+                rule
+            );
+        }
+    }
+}
+
+/// Name of the table that a header line like `[erc]` or `[[board]]` starts, and whether the
+/// table is an entry in an array of tables
+fn table_header(line: &str) -> Option<(bool, &str)> {
+    let line = line.trim();
+    if let Some(rest) = line.strip_prefix("[[") {
+        return rest.split("]]").next().map(|name| (true, name.trim()));
+    }
+    line.strip_prefix('[')
+        .and_then(|rest| rest.split(']').next())
+        .map(|name| (false, name.trim()))
+}
+
+/// Span of a line without the whitespace around it
+fn line_tag(offset: usize, line: &str) -> SrcTag {
+    let start = offset + line.len() - line.trim_start().len();
+    SrcTag::span(0, start, start + line.trim().len())
+}
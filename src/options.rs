@@ -0,0 +1,54 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use std::path::PathBuf;
+
+use erc::NetCheck;
+use lint::{Lint, LintLevel};
+use manifest::Manifest;
+use parse::component::PinType;
+
+/// How the electrical rules check treats a connection between two pin types
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErcLevel {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Settings that change how a circuit is compiled
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Directories to search for required modules that aren't next to the requiring file,
+    /// ahead of `NETMUNCHER_PATH` and the project manifest's `lib-paths`
+    pub lib_paths: Vec<PathBuf>,
+    /// Component to compile instead of the manifest's `top`, or `Main`
+    pub top: Option<String>,
+    /// ERC results for connections between two pin types, which take precedence over
+    /// the project manifest's
+    pub erc_overrides: Vec<(PinType, PinType, ErcLevel)>,
+    /// ERC results for mapping a parent's pin of one type to a child's pin of another,
+    /// which take precedence over the project manifest's
+    pub erc_mapping_overrides: Vec<(PinType, PinType, ErcLevel)>,
+    /// Levels of the checks of whole nets, which take precedence over the project manifest's
+    pub net_check_levels: Vec<(NetCheck, ErcLevel)>,
+    /// Levels of lints, which take precedence over the project manifest's
+    pub lint_levels: Vec<(Lint, LintLevel)>,
+    /// Project manifest to use rather than searching for one near the compiled file
+    pub manifest: Option<Manifest>,
+    /// Whether warnings that aren't allowed by comments fail the compile
+    pub deny_warnings: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Default::default()
+    }
+}
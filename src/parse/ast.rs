@@ -7,14 +7,18 @@
 // copied, modified, or distributed except according to those terms.
 //
 
-use parse::component::{PinNum, PinType, UnitPin};
+use erc::NetCheck;
+use options::ErcLevel;
+use parse::bundle::BundleRef;
+use parse::component::{Argument, Condition, ParamType, ParamValue, PinNum, PinType, UnitPin,
+                       ValueExpr, Voltage};
 use parse::source::SrcTag;
 
 pub trait Tagged {
     fn tag(&self) -> SrcTag;
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct AbstractPin {
     pub tag: SrcTag,
     pub name: String,
@@ -27,12 +31,35 @@ impl Tagged for AbstractPin {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
+pub struct BundleDef {
+    pub tag: SrcTag,
+    pub name: String,
+    pub members: Vec<(String, PinType)>,
+}
+
+impl Tagged for BundleDef {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+impl Tagged for BundleRef {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
 pub struct ComponentDef {
     pub tag: SrcTag,
     pub name: String,
     pub is_abstract: bool,
+    pub parameters: Vec<ParameterDef>,
+    pub extends: Option<String>,
     pub params: Vec<Ast>,
+    #[new(default)]
+    pub is_private: bool,
 }
 
 impl Tagged for ComponentDef {
@@ -41,12 +68,13 @@ impl Tagged for ComponentDef {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct ConcretePin {
     pub tag: SrcTag,
     pub name: String,
     pub typ: PinType,
-    pub num: PinNum,
+    pub nums: Vec<PinNum>,
+    pub voltage: Option<Voltage>,
 }
 
 impl Tagged for ConcretePin {
@@ -55,7 +83,7 @@ impl Tagged for ConcretePin {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct Connect {
     pub tag: SrcTag,
     pub left: Vec<String>,
@@ -68,7 +96,42 @@ impl Tagged for Connect {
     }
 }
 
-#[derive(Debug, new)]
+/// Overrides of ERC results for the whole project
+#[derive(Clone, Debug, new)]
+pub struct ErcBlock {
+    pub tag: SrcTag,
+    pub rules: Vec<ErcRule>,
+}
+
+impl Tagged for ErcBlock {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ErcRule {
+    /// Two pins connected by a net
+    Connection(PinType, PinType, ErcLevel),
+    /// A parent's pin mapped to a child's pin
+    Mapping(PinType, PinType, ErcLevel),
+    Net(NetCheck, ErcLevel),
+}
+
+#[derive(Clone, Debug, new)]
+pub struct Field {
+    pub tag: SrcTag,
+    pub name: String,
+    pub value: ValueExpr,
+}
+
+impl Tagged for Field {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
 pub struct Footprint {
     pub tag: SrcTag,
     pub footprint: String,
@@ -80,10 +143,41 @@ impl Tagged for Footprint {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
+pub struct ForLoop {
+    pub tag: SrcTag,
+    pub var: String,
+    pub range: Vec<u32>,
+    pub body: Vec<Ast>,
+}
+
+impl Tagged for ForLoop {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
+pub struct IfBlock {
+    pub tag: SrcTag,
+    pub condition: Condition,
+    pub body: Vec<Ast>,
+    pub else_body: Vec<Ast>,
+}
+
+impl Tagged for IfBlock {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
 pub struct InstanceDef {
     pub tag: SrcTag,
     pub name: String,
+    pub label: Option<String>,
+    pub count: Option<usize>,
+    pub arguments: Vec<Argument>,
     pub parameters: Vec<Ast>,
 }
 
@@ -93,10 +187,11 @@ impl Tagged for InstanceDef {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct ConnectionMap {
     pub tag: SrcTag,
-    pub connections: Vec<(String, String)>,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
 }
 
 impl Tagged for ConnectionMap {
@@ -105,10 +200,13 @@ impl Tagged for ConnectionMap {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct Nets {
     pub tag: SrcTag,
     pub nets: Vec<String>,
+    pub bundle: Option<String>,
+    /// Voltage of a global power net, in millivolts
+    pub voltage: Option<u32>,
 }
 
 impl Tagged for Nets {
@@ -117,7 +215,47 @@ impl Tagged for Nets {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
+pub struct PackageDef {
+    pub tag: SrcTag,
+    pub name: String,
+    pub footprints: Vec<Footprint>,
+    pub pins: Vec<(String, Vec<PinNum>)>,
+}
+
+impl Tagged for PackageDef {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
+pub struct PackageRef {
+    pub tag: SrcTag,
+    pub package: String,
+}
+
+impl Tagged for PackageRef {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
+pub struct ParameterDef {
+    pub tag: SrcTag,
+    pub name: String,
+    pub typ: Option<ParamType>,
+    pub default: Option<ParamValue>,
+}
+
+impl Tagged for ParameterDef {
+    fn tag(&self) -> SrcTag {
+        self.tag
+    }
+}
+
+#[derive(Clone, Debug, new)]
 pub struct Prefix {
     pub tag: SrcTag,
     pub prefix: String,
@@ -129,10 +267,12 @@ impl Tagged for Prefix {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct Require {
     pub tag: SrcTag,
     pub module: String,
+    /// Name of the namespace to require the module into, if any
+    pub namespace: Option<String>,
 }
 
 impl Tagged for Require {
@@ -141,10 +281,10 @@ impl Tagged for Require {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct Value {
     pub tag: SrcTag,
-    pub value: String,
+    pub value: ValueExpr,
 }
 
 impl Tagged for Value {
@@ -153,7 +293,7 @@ impl Tagged for Value {
     }
 }
 
-#[derive(Debug, new)]
+#[derive(Clone, Debug, new)]
 pub struct Unit {
     pub tag: SrcTag,
     pub pins: Vec<UnitPin>,
@@ -165,16 +305,24 @@ impl Tagged for Unit {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Ast {
     AbstractPins(Vec<AbstractPin>),
+    BundleDef(BundleDef),
+    BundlePins(Vec<BundleRef>),
     ComponentDef(ComponentDef),
     ConcretePins(Vec<ConcretePin>),
     ConnectionMap(ConnectionMap),
     Connect(Connect),
+    Erc(ErcBlock),
+    Field(Field),
     Footprint(Footprint),
+    For(ForLoop),
+    If(IfBlock),
     InstanceDef(InstanceDef),
     Nets(Nets),
+    PackageDef(PackageDef),
+    PackageRef(PackageRef),
     Prefix(Prefix),
     Require(Require),
     Value(Value),
@@ -186,13 +334,21 @@ impl Tagged for Ast {
         use self::Ast::*;
         match *self {
             AbstractPins(ref pins) => pins[0].tag(),
+            BundleDef(ref def) => def.tag(),
+            BundlePins(ref pins) => pins[0].tag(),
             ComponentDef(ref def) => def.tag(),
             ConcretePins(ref pins) => pins[0].tag(),
             ConnectionMap(ref map) => map.tag(),
             Connect(ref conn) => conn.tag(),
+            Erc(ref block) => block.tag(),
+            Field(ref field) => field.tag(),
             Footprint(ref footprint) => footprint.tag(),
+            For(ref for_loop) => for_loop.tag(),
+            If(ref if_block) => if_block.tag(),
             InstanceDef(ref def) => def.tag(),
             Nets(ref nets) => nets.tag(),
+            PackageDef(ref def) => def.tag(),
+            PackageRef(ref package) => package.tag(),
             Prefix(ref prefix) => prefix.tag(),
             Require(ref require) => require.tag(),
             Value(ref value) => value.tag(),
@@ -200,3 +356,78 @@ impl Tagged for Ast {
         }
     }
 }
+
+impl Ast {
+    /// Replaces a for loop variable with its value for one iteration of the loop. An `if`
+    /// block that tests the variable is replaced by the branch that the value picks.
+    pub fn substitute(self, var: &str, value: u32) -> Vec<Ast> {
+        let index = format!("[{}]", var);
+        let names = |names: Vec<String>| -> Vec<String> {
+            names
+                .into_iter()
+                .map(|name| name.replace(&index, &value.to_string()))
+                .collect()
+        };
+        let expr = |expr: ValueExpr| match expr {
+            ValueExpr::Param(ref name) if name == var => {
+                ValueExpr::Literal(ParamValue::Num(value))
+            }
+            expr => expr,
+        };
+        let body = |body: Vec<Ast>| -> Vec<Ast> {
+            body.into_iter().flat_map(|ast| ast.substitute(var, value)).collect()
+        };
+        let ast = match self {
+            Ast::ConnectionMap(map) => {
+                Ast::ConnectionMap(ConnectionMap::new(map.tag, names(map.left), names(map.right)))
+            }
+            Ast::Connect(conn) => {
+                Ast::Connect(Connect::new(conn.tag, names(conn.left), names(conn.right)))
+            }
+            Ast::For(for_loop) => {
+                if for_loop.var == var {
+                    // The inner loop's variable shadows this one
+                    Ast::For(for_loop)
+                } else {
+                    Ast::For(ForLoop::new(
+                        for_loop.tag,
+                        for_loop.var,
+                        for_loop.range,
+                        body(for_loop.body),
+                    ))
+                }
+            }
+            Ast::If(if_block) => {
+                let condition = if_block.condition;
+                if condition.param == var {
+                    let holds = (ParamValue::Num(value) == condition.value) == condition.equal;
+                    return body(if holds { if_block.body } else { if_block.else_body });
+                }
+                Ast::If(IfBlock::new(
+                    if_block.tag,
+                    condition,
+                    body(if_block.body),
+                    body(if_block.else_body),
+                ))
+            }
+            Ast::InstanceDef(def) => Ast::InstanceDef(InstanceDef::new(
+                def.tag,
+                def.name,
+                def.label.map(|label| label.replace(&index, &value.to_string())),
+                def.count,
+                def.arguments
+                    .into_iter()
+                    .map(|arg| Argument::new(arg.name, expr(arg.value)))
+                    .collect(),
+                body(def.parameters),
+            )),
+            Ast::Nets(nets) => {
+                Ast::Nets(Nets::new(nets.tag, names(nets.nets), nets.bundle, nets.voltage))
+            },
+            Ast::Field(field) => Ast::Field(Field::new(field.tag, field.name, expr(field.value))),
+            Ast::Value(val) => Ast::Value(Value::new(val.tag, expr(val.value))),
+            other => other,
+        };
+        vec![ast]
+    }
+}
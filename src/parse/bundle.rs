@@ -0,0 +1,342 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Bundles group related signals (such as the lines of an I2C bus) under a single name.
+//! They get expanded into ordinary pins and nets named `bundle.MEMBER` once every module
+//! has been loaded, so validation and instantiation never need to know about them.
+
+use std::collections::{BTreeMap, HashSet};
+
+use diagnostic::code;
+use error;
+use parse::component::{Component, Instance, Pin, PinNum, PinType};
+use parse::source::{Sources, SrcTag};
+
+#[derive(Debug, new)]
+pub struct Bundle {
+    pub tag: SrcTag,
+    pub name: String,
+    pub members: Vec<(String, PinType)>,
+}
+
+/// A pin or net that is typed by a bundle
+#[derive(Clone, Debug, new)]
+pub struct BundleRef {
+    pub tag: SrcTag,
+    pub name: String,
+    pub bundle: String,
+}
+
+pub fn member_name(name: &str, member: &str) -> String {
+    format!("{}.{}", name, member)
+}
+
+pub struct BundleExpander<'input> {
+    sources: &'input Sources,
+    bundles: BTreeMap<&'input str, &'input Bundle>,
+    global_nets: &'input [BundleRef],
+}
+
+impl<'input> BundleExpander<'input> {
+    pub fn new(
+        sources: &'input Sources,
+        bundles: &'input [Bundle],
+        global_nets: &'input [BundleRef],
+    ) -> error::Result<BundleExpander<'input>> {
+        let mut errors = error::Errors::new();
+        let mut bundle_map = BTreeMap::new();
+        for bundle in bundles {
+            if bundle_map.contains_key(bundle.name.as_str()) {
+                report!(
+                    errors,
+                    sources,
+                    bundle.tag,
+                    code::DUPLICATE,
+                    "bundle {} is defined more than once",
+                    bundle.name
+                );
+                continue;
+            }
+            if bundle.members.is_empty() {
+                report!(
+                    errors,
+                    sources,
+                    bundle.tag,
+                    code::BUNDLE,
+                    "bundle {} has no members",
+                    bundle.name
+                );
+            }
+            let mut members = HashSet::new();
+            for &(ref member, _) in &bundle.members {
+                if !members.insert(member) {
+                    report!(
+                        errors,
+                        sources,
+                        bundle.tag,
+                        code::DUPLICATE,
+                        "bundle {} has more than one member named {}",
+                        bundle.name,
+                        member
+                    );
+                }
+            }
+            bundle_map.insert(bundle.name.as_str(), bundle);
+        }
+        errors.into_result()?;
+        Ok(BundleExpander {
+            sources: sources,
+            bundles: bundle_map,
+            global_nets: global_nets,
+        })
+    }
+
+    pub fn expand(
+        &self,
+        global_nets: &mut Vec<String>,
+        components: &mut Vec<Component>,
+    ) -> error::Result<()> {
+        let mut errors = error::Errors::new();
+        for net in self.global_nets {
+            if let Some(bundle) = errors.check(Some(net.tag), self.find_bundle(net)) {
+                for member in &bundle.members {
+                    let name = member_name(&net.name, &member.0);
+                    if global_nets.contains(&name) {
+                        report!(
+                            errors,
+                            self.sources,
+                            net.tag,
+                            code::DUPLICATE,
+                            "global net {} is declared more than once",
+                            name
+                        );
+                    } else {
+                        global_nets.push(name);
+                    }
+                }
+            }
+        }
+
+        let child_pins: BTreeMap<String, Vec<BundleRef>> = components
+            .iter()
+            .map(|c| (c.name().into(), c.bundle_pins.clone()))
+            .collect();
+        for component in components.iter_mut() {
+            self.expand_component(component, &child_pins, &mut errors);
+        }
+        errors.into_result()
+    }
+
+    fn find_bundle(&self, bundle_ref: &BundleRef) -> error::Result<&'input Bundle> {
+        match self.bundles.get(bundle_ref.bundle.as_str()) {
+            Some(bundle) => Ok(bundle),
+            None => {
+                err_at!(
+                    self.sources,
+                    bundle_ref.tag,
+                    code::BUNDLE,
+                    "cannot find bundle named {}",
+                    bundle_ref.bundle
+                );
+            }
+        }
+    }
+
+    fn expand_component(
+        &self,
+        component: &mut Component,
+        child_pins: &BTreeMap<String, Vec<BundleRef>>,
+        errors: &mut error::Errors,
+    ) {
+        // Names of everything in this component's scope that's typed by a bundle
+        let mut scope: BTreeMap<String, &'input Bundle> = BTreeMap::new();
+        for net in self.global_nets {
+            if let Ok(bundle) = self.find_bundle(net) {
+                scope.insert(net.name.clone(), bundle);
+            }
+        }
+
+        for pin in component.bundle_pins.clone() {
+            let bundle = match errors.check(Some(pin.tag), self.find_bundle(&pin)) {
+                Some(bundle) => bundle,
+                None => continue,
+            };
+            for &(ref member, typ) in &bundle.members {
+                let nums = vec![PinNum::from_index(component.abstract_pins().len() + 1)];
+                let pin = Pin::new(pin.tag, member_name(&pin.name, member), typ, nums);
+                component.add_pin(self.sources, pin, errors);
+            }
+            scope.insert(pin.name, bundle);
+        }
+        let branch_nets = component.branches.iter_mut().map(|b| (&mut b.nets, &b.bundle_nets));
+        for (nets, bundle_nets) in ::std::iter::once((&mut component.nets, &component.bundle_nets))
+            .chain(branch_nets)
+        {
+            for net in bundle_nets {
+                let bundle = match errors.check(Some(net.tag), self.find_bundle(net)) {
+                    Some(bundle) => bundle,
+                    None => continue,
+                };
+                for &(ref member, _) in &bundle.members {
+                    nets.add_net(self.sources, member_name(&net.name, member), net.tag, errors);
+                }
+                scope.insert(net.name.clone(), bundle);
+            }
+        }
+
+        for instance in &mut component.instances {
+            if let Some(pins) = child_pins.get(&instance.name) {
+                self.expand_instance(instance, pins, &scope, errors);
+            }
+        }
+
+        let connects = self.expand_connects(component.tag, &mut component.connects, &scope, errors);
+        component.connects = connects;
+        for branch in &mut component.branches {
+            let connects = self.expand_connects(branch.tag, &mut branch.connects, &scope, errors);
+            branch.connects = connects;
+        }
+    }
+
+    fn expand_connects(
+        &self,
+        tag: SrcTag,
+        connects: &mut Vec<(String, String)>,
+        scope: &BTreeMap<String, &'input Bundle>,
+        errors: &mut error::Errors,
+    ) -> Vec<(String, String)> {
+        let mut expanded = Vec::new();
+        for (left, right) in connects.drain(..) {
+            match (scope.get(&left), scope.get(&right)) {
+                (None, None) => expanded.push((left, right)),
+                (Some(left_bundle), Some(right_bundle))
+                    if left_bundle.name == right_bundle.name =>
+                {
+                    for &(ref member, _) in &left_bundle.members {
+                        expanded.push((member_name(&left, member), member_name(&right, member)));
+                    }
+                }
+                _ => {
+                    report!(
+                        errors,
+                        self.sources,
+                        tag,
+                        code::BUNDLE,
+                        "cannot connect {} to {} because they aren't bundles of the same type",
+                        left,
+                        right
+                    );
+                }
+            }
+        }
+        expanded
+    }
+
+    fn expand_instance(
+        &self,
+        instance: &mut Instance,
+        pins: &[BundleRef],
+        scope: &BTreeMap<String, &'input Bundle>,
+        errors: &mut error::Errors,
+    ) {
+        let tag = Some(instance.tag);
+        let mut connections = Vec::new();
+        for (pin, net) in instance.connections.drain(..) {
+            if let Some(pin_ref) = pins.iter().find(|p| p.name == pin) {
+                let bundle = match errors.check(Some(pin_ref.tag), self.find_bundle(pin_ref)) {
+                    Some(bundle) => bundle,
+                    None => continue,
+                };
+                let nets = self.member_nets(instance.tag, &instance.name, pin_ref, &net, scope);
+                if let Some(nets) = errors.check(tag, nets) {
+                    for (&(ref member, _), net) in bundle.members.iter().zip(nets) {
+                        connections.push((member_name(&pin, member), net));
+                    }
+                }
+            } else {
+                connections.push((pin, net));
+            }
+        }
+        instance.connections = connections;
+
+        let mut spread_connections = Vec::new();
+        for (pin, nets) in instance.spread_connections.drain(..) {
+            if let Some(pin_ref) = pins.iter().find(|p| p.name == pin) {
+                let bundle = match errors.check(Some(pin_ref.tag), self.find_bundle(pin_ref)) {
+                    Some(bundle) => bundle,
+                    None => continue,
+                };
+                let mut expanded: Vec<Vec<String>> =
+                    bundle.members.iter().map(|_| Vec::new()).collect();
+                for net in &nets {
+                    let member_nets =
+                        self.member_nets(instance.tag, &instance.name, pin_ref, net, scope);
+                    if let Some(member_nets) = errors.check(tag, member_nets) {
+                        for (index, member_net) in member_nets.into_iter().enumerate() {
+                            expanded[index].push(member_net);
+                        }
+                    }
+                }
+                for (&(ref member, _), nets) in bundle.members.iter().zip(expanded) {
+                    spread_connections.push((member_name(&pin, member), nets));
+                }
+            } else {
+                spread_connections.push((pin, nets));
+            }
+        }
+        instance.spread_connections = spread_connections;
+    }
+
+    /// Returns the nets to connect each member of a bundle pin to
+    fn member_nets(
+        &self,
+        tag: SrcTag,
+        instance_name: &str,
+        pin: &BundleRef,
+        net: &str,
+        scope: &BTreeMap<String, &'input Bundle>,
+    ) -> error::Result<Vec<String>> {
+        let bundle = self.find_bundle(pin)?;
+        if net == "noconnect" {
+            return Ok(bundle.members.iter().map(|_| net.into()).collect());
+        }
+        match scope.get(net) {
+            Some(net_bundle) if net_bundle.name == bundle.name => Ok(bundle
+                .members
+                .iter()
+                .map(|&(ref member, _)| member_name(net, member))
+                .collect()),
+            Some(net_bundle) => {
+                err_at!(
+                    self.sources,
+                    tag,
+                    code::BUNDLE,
+                    "cannot connect {} bundle pin {} of {} to {}, which is a {} bundle",
+                    bundle.name,
+                    pin.name,
+                    instance_name,
+                    net,
+                    net_bundle.name
+                );
+            }
+            None => {
+                err_at!(
+                    self.sources,
+                    tag,
+                    code::BUNDLE,
+                    "cannot connect {} bundle pin {} of {} to {}, which is not a bundle",
+                    bundle.name,
+                    pin.name,
+                    instance_name,
+                    net
+                );
+            }
+        }
+    }
+}
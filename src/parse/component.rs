@@ -7,9 +7,15 @@
 // copied, modified, or distributed except according to those terms.
 //
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
+use diagnostic::code;
 use error;
+use parse::bundle::BundleRef;
+use parse::namespace;
 use parse::source::{Sources, SrcTag};
 use serde::{Serialize, Serializer};
 
@@ -27,15 +33,131 @@ pub enum PinType {
     NoConnect,
 }
 
+impl PinType {
+    /// Name of the pin type in source files
+    pub fn keyword(&self) -> &'static str {
+        use self::PinType::*;
+        match *self {
+            Input => "input",
+            Output => "output",
+            Passive => "passive",
+            PowerIn => "power_in",
+            PowerOut => "power_out",
+            Tristate => "tristate",
+            Bidirectional => "bidirectional",
+            NoConnect => "noconnect",
+        }
+    }
+}
+
+/// Voltage of a pin, in millivolts
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub struct PinNum(pub u32);
+pub enum Voltage {
+    /// A `power_out` pin that supplies its net at this voltage
+    Supplies(u32),
+    /// An `input` or `power_in` pin that works with nets from the first voltage to the second
+    Accepts(u32, u32),
+}
+
+impl Voltage {
+    /// The range accepted by inputs of a logic family, such as `lvcmos33`
+    pub fn for_family(family: &str) -> Option<Voltage> {
+        Some(match family {
+            "lvcmos18" => Voltage::Accepts(1650, 1950),
+            "lvcmos25" => Voltage::Accepts(2300, 2700),
+            "lvcmos33" => Voltage::Accepts(3000, 3600),
+            "cmos5" => Voltage::Accepts(4500, 5500),
+            "ttl" => Voltage::Accepts(2000, 5500),
+            _ => return None,
+        })
+    }
+
+    /// Whether a pin of the given type can have this voltage
+    pub fn suits(&self, typ: PinType) -> bool {
+        match *self {
+            Voltage::Supplies(_) => typ == PinType::PowerOut,
+            Voltage::Accepts(..) => typ == PinType::Input || typ == PinType::PowerIn,
+        }
+    }
+}
+
+impl fmt::Display for Voltage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Voltage::Supplies(millivolts) => write!(f, "{}", format_voltage(millivolts)),
+            Voltage::Accepts(min, max) => {
+                write!(f, "{} to {}", format_voltage(min), format_voltage(max))
+            }
+        }
+    }
+}
+
+/// Writes a voltage in millivolts the way it's written in source files, such as `3.3V`
+pub fn format_voltage(millivolts: u32) -> String {
+    let fraction = format!("{:03}", millivolts % 1000);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}V", millivolts / 1000)
+    } else {
+        format!("{}.{}V", millivolts / 1000, fraction)
+    }
+}
+
+impl FromStr for PinType {
+    type Err = error::Error;
+
+    fn from_str(s: &str) -> error::Result<PinType> {
+        use self::PinType::*;
+        Ok(match s {
+            "input" => Input,
+            "output" => Output,
+            "passive" => Passive,
+            "power_in" => PowerIn,
+            "power_out" => PowerOut,
+            "tristate" => Tristate,
+            "bidirectional" => Bidirectional,
+            "noconnect" => NoConnect,
+            _ => {
+                err!("unknown pin type {}", s);
+            }
+        })
+    }
+}
+
+/// Pin number on a footprint, which is either a plain number or alphanumeric, such as a BGA
+/// ball (A1, K12) or an exposed pad (EP)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PinNum(pub String);
+
+impl PinNum {
+    pub fn from_index(num: usize) -> PinNum {
+        PinNum(num.to_string())
+    }
+
+    pub fn as_number(&self) -> Option<u32> {
+        if self.0.chars().all(|c| c.is_digit(10)) {
+            self.0.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// The number a pin starts with, so both 1A and 1B are at position 1
+    pub fn position(&self) -> Option<u32> {
+        let digits: String = self.0.chars().take_while(|c| c.is_digit(10)).collect();
+        digits.parse().ok()
+    }
+}
 
 impl Serialize for PinNum {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_u32(self.0)
+        match self.as_number() {
+            Some(num) => serializer.serialize_u32(num),
+            None => serializer.serialize_str(&self.0),
+        }
     }
 }
 
@@ -45,46 +167,243 @@ impl fmt::Display for PinNum {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParamType {
+    String,
+    Number,
+}
+
+impl fmt::Display for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ParamType::String => write!(f, "string"),
+            ParamType::Number => write!(f, "number"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ParamValue {
+    Str(String),
+    Num(u32),
+}
+
+impl ParamValue {
+    pub fn typ(&self) -> ParamType {
+        match *self {
+            ParamValue::Str(_) => ParamType::String,
+            ParamValue::Num(_) => ParamType::Number,
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ParamValue::Str(ref s) => write!(f, "{}", s),
+            ParamValue::Num(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Parameter values bound to a component's parameter names for a single instantiation
+pub type Bindings = BTreeMap<String, ParamValue>;
+
+/// Either a literal value, or a reference to a parameter of the enclosing component
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ValueExpr {
+    Literal(ParamValue),
+    Param(String),
+}
+
+impl ValueExpr {
+    pub fn resolve(&self, bindings: &Bindings) -> ParamValue {
+        self.try_resolve(bindings).expect("validation should catch this")
+    }
+
+    /// Resolves the value, or returns `None` if it refers to a parameter that isn't bound
+    pub fn try_resolve(&self, bindings: &Bindings) -> Option<ParamValue> {
+        match *self {
+            ValueExpr::Literal(ref value) => Some(value.clone()),
+            ValueExpr::Param(ref name) => bindings.get(name).cloned(),
+        }
+    }
+}
+
+/// Comparison of a parameter against a literal that guards a branch of an `if` block
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Condition {
+    pub param: String,
+    pub equal: bool,
+    pub value: ParamValue,
+}
+
+impl Condition {
+    pub fn new(param: String, equal: bool, value: ParamValue) -> Condition {
+        Condition {
+            param: param,
+            equal: equal,
+            value: value,
+        }
+    }
+
+    pub fn negate(&self) -> Condition {
+        Condition::new(self.param.clone(), !self.equal, self.value.clone())
+    }
+
+    /// Conditions on parameters that aren't bound never hold, so neither branch is taken
+    pub fn holds(&self, bindings: &Bindings) -> bool {
+        match bindings.get(&self.param) {
+            Some(value) => (*value == self.value) == self.equal,
+            None => false,
+        }
+    }
+}
+
+/// The instances, nets and connects of one branch of an `if` block, which are only part of
+/// the component when all of the branch's conditions hold
+#[derive(Clone, Debug)]
+pub struct Branch {
+    pub tag: SrcTag,
+    /// Conditions of the enclosing branches, followed by this branch's own
+    pub conditions: Vec<Condition>,
+    /// Indices of the component's instances that were declared in the branch
+    pub instances: Range<usize>,
+    pub nets: NetList,
+    pub bundle_nets: Vec<BundleRef>,
+    pub connects: Vec<(String, String)>,
+}
+
+impl Branch {
+    pub fn new(tag: SrcTag, conditions: Vec<Condition>, first_instance: usize) -> Branch {
+        Branch {
+            tag: tag,
+            conditions: conditions,
+            instances: first_instance..first_instance,
+            nets: Default::default(),
+            bundle_nets: Vec::new(),
+            connects: Vec::new(),
+        }
+    }
+
+    pub fn holds(&self, bindings: &Bindings) -> bool {
+        self.conditions.iter().all(|c| c.holds(bindings))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub tag: SrcTag,
+    pub name: String,
+    pub typ: ParamType,
+    pub default: Option<ParamValue>,
+}
+
+impl Parameter {
+    pub fn new(
+        tag: SrcTag,
+        name: String,
+        typ: ParamType,
+        default: Option<ParamValue>,
+    ) -> Parameter {
+        Parameter {
+            tag: tag,
+            name: name,
+            typ: typ,
+            default: default,
+        }
+    }
+}
+
+/// A value passed to a component parameter, either by position or by name
+#[derive(Clone, Debug)]
+pub struct Argument {
+    pub name: Option<String>,
+    pub value: ValueExpr,
+}
+
+impl Argument {
+    pub fn new(name: Option<String>, value: ValueExpr) -> Argument {
+        Argument {
+            name: name,
+            value: value,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct UnitPin {
+    pub tag: SrcTag,
     pub name: String,
     pub typ: PinType,
     pub nums: Vec<PinNum>,
+    pub voltage: Option<Voltage>,
 }
 
 impl UnitPin {
-    pub fn new(name: String, typ: PinType, nums: Vec<PinNum>) -> UnitPin {
+    pub fn new(
+        tag: SrcTag,
+        name: String,
+        typ: PinType,
+        nums: Vec<PinNum>,
+        voltage: Option<Voltage>,
+    ) -> UnitPin {
         UnitPin {
+            tag: tag,
             name: name,
             typ: typ,
             nums: nums,
+            voltage: voltage,
         }
     }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Pin {
+    /// Where the pin is declared
+    pub tag: SrcTag,
     pub name: String,
     pub typ: PinType,
-    pub num: PinNum,
+    /// Pads on the footprint for this pin, which all connect to the same net
+    pub nums: Vec<PinNum>,
+    pub voltage: Option<Voltage>,
 }
 
 impl Pin {
-    pub fn new(name: String, typ: PinType, num: PinNum) -> Pin {
+    pub fn new(tag: SrcTag, name: String, typ: PinType, nums: Vec<PinNum>) -> Pin {
         Pin {
+            tag: tag,
             name: name,
             typ: typ,
-            num: num,
+            nums: nums,
+            voltage: None,
         }
     }
+
+    pub fn with_voltage(mut self, voltage: Option<Voltage>) -> Pin {
+        self.voltage = voltage;
+        self
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Instance {
     pub tag: SrcTag,
     pub name: String,
-    pub value: Option<String>,
+    /// Name given to this instance in the hierarchy, such as `r_pullup` in `R r_pullup { ... }`
+    pub label: Option<String>,
+    pub value: Option<ValueExpr>,
+    /// Package chosen for this instance, instead of the component's default footprint
+    pub package: Option<String>,
+    /// Overrides of the component's fields for this instance
+    pub fields: Vec<(String, ValueExpr)>,
+    pub arguments: Vec<Argument>,
+    /// Number of copies when this is an instance array
+    pub array: Option<usize>,
+    /// Connections shared by every copy of the instance
     pub connections: Vec<(String, String)>,
+    /// Connections of instance arrays that map each copy to a different net
+    pub spread_connections: Vec<(String, Vec<String>)>,
 }
 
 impl Instance {
@@ -92,11 +411,38 @@ impl Instance {
         Instance {
             tag: tag,
             name: name,
+            label: None,
             value: None,
+            package: None,
+            fields: Vec::new(),
+            arguments: Vec::new(),
+            array: None,
             connections: Vec::new(),
+            spread_connections: Vec::new(),
         }
     }
 
+    pub fn copies(&self) -> usize {
+        self.array.unwrap_or(1)
+    }
+
+    /// Name of the given copy of this instance for use in messages
+    pub fn copy_name(&self, copy: usize) -> String {
+        match (self.copy_label(copy), self.array) {
+            (Some(label), _) => format!("{} {}", self.name, label),
+            (None, Some(_)) => format!("{}[{}]", self.name, copy),
+            (None, None) => self.name.clone(),
+        }
+    }
+
+    /// Label of the given copy of this instance, if it was named
+    pub fn copy_label(&self, copy: usize) -> Option<String> {
+        self.label.as_ref().map(|label| match self.array {
+            Some(_) => format!("{}[{}]", label, copy),
+            None => label.clone(),
+        })
+    }
+
     pub fn find_connection(&self, pin_name: &str) -> Option<&String> {
         self.connections
             .iter()
@@ -104,8 +450,16 @@ impl Instance {
             .map(|tup| &tup.1)
     }
 
-    pub fn value(&self) -> Option<&str> {
-        self.value.as_ref().map(|v| v as &str)
+    pub fn find_copy_connection(&self, copy: usize, pin_name: &str) -> Option<&String> {
+        self.spread_connections
+            .iter()
+            .find(|&&(ref name, _)| *name == pin_name)
+            .map(|tup| &tup.1[copy])
+            .or_else(|| self.find_connection(pin_name))
+    }
+
+    pub fn value(&self) -> Option<&ValueExpr> {
+        self.value.as_ref()
     }
 }
 
@@ -115,23 +469,54 @@ pub struct PinMap {
 }
 
 impl PinMap {
-    pub fn add_pin(&mut self, pin: Pin) -> error::Result<()> {
-        if pin.num == PinNum(0) {
-            err!("pin numbers must start at 1");
+    /// Adds the pin, unless it clashes with itself or the pins already added
+    pub fn add_pin(&mut self, sources: &Sources, pin: Pin, errors: &mut error::Errors) {
+        let mut clashes = false;
+        if pin.nums.iter().any(|num| num.as_number() == Some(0)) {
+            report!(errors, sources, pin.tag, code::PIN, "pin numbers must start at 1");
+            clashes = true;
         }
         if self.find_by_name(&pin.name).is_some() {
-            err!(format!("duplicate pin named {}", pin.name));
-        }
-        if let Some(other) = self.find_by_num(pin.num) {
-            err!(
-                "pin number {} assigned to multiple names: {}, {}",
-                pin.num,
-                pin.name,
-                other.name
+            report!(
+                errors,
+                sources,
+                pin.tag,
+                code::DUPLICATE,
+                "duplicate pin named {}",
+                pin.name
             );
+            clashes = true;
+        }
+        for (index, num) in pin.nums.iter().enumerate() {
+            if pin.nums[0..index].contains(num) {
+                report!(
+                    errors,
+                    sources,
+                    pin.tag,
+                    code::DUPLICATE,
+                    "pin number {} assigned to {} more than once",
+                    num,
+                    pin.name
+                );
+                clashes = true;
+            }
+            if let Some(other) = self.find_by_num(num) {
+                report!(
+                    errors,
+                    sources,
+                    pin.tag,
+                    code::DUPLICATE,
+                    "pin number {} assigned to multiple names: {}, {}",
+                    num,
+                    pin.name,
+                    other.name
+                );
+                clashes = true;
+            }
+        }
+        if !clashes {
+            self.pins.push(pin);
         }
-        self.pins.push(pin);
-        Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
@@ -142,8 +527,8 @@ impl PinMap {
         self.pins.len()
     }
 
-    pub fn find_by_num(&self, num: PinNum) -> Option<&Pin> {
-        self.pins.iter().find(|p: &&Pin| p.num == num)
+    pub fn find_by_num(&self, num: &PinNum) -> Option<&Pin> {
+        self.pins.iter().find(|p: &&Pin| p.nums.contains(num))
     }
 
     pub fn find_by_name(&self, name: &str) -> Option<&Pin> {
@@ -169,29 +554,36 @@ impl IntoIterator for PinMap {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct NetList {
     nets: Vec<String>,
+    /// Where each net is declared
+    tags: Vec<SrcTag>,
 }
 
 impl NetList {
-    pub fn add_net(&mut self, net: String) -> error::Result<()> {
+    pub fn add_net(
+        &mut self,
+        sources: &Sources,
+        net: String,
+        tag: SrcTag,
+        errors: &mut error::Errors,
+    ) {
         if self.exists(&net) {
-            err!("duplicate net named {}", net)
+            report!(errors, sources, tag, code::DUPLICATE, "duplicate net named {}", net);
         } else {
             self.nets.push(net);
-            Ok(())
+            self.tags.push(tag);
         }
     }
 
-    pub fn extend<I>(&mut self, iterator: I) -> error::Result<()>
+    pub fn extend<I>(&mut self, sources: &Sources, iterator: I, errors: &mut error::Errors)
     where
-        I: Iterator<Item = String>,
+        I: Iterator<Item = (String, SrcTag)>,
     {
-        for net in iterator {
-            self.add_net(net)?;
+        for (net, tag) in iterator {
+            self.add_net(sources, net, tag, errors);
         }
-        Ok(())
     }
 
     pub fn is_empty(&self) -> bool {
@@ -209,6 +601,10 @@ impl NetList {
     pub fn iter<'a>(&'a self) -> ::std::slice::Iter<'a, String> {
         self.into_iter()
     }
+
+    pub fn tag(&self, net: &str) -> Option<SrcTag> {
+        self.nets.iter().position(|n| n == net).map(|index| self.tags[index])
+    }
 }
 
 impl<'a> IntoIterator for &'a NetList {
@@ -231,42 +627,96 @@ impl Unit {
     }
 }
 
-#[derive(Debug)]
+/// Alternative footprint for a component, with its own mapping of pins to pin numbers
+#[derive(Clone, Debug)]
+pub struct Package {
+    pub tag: SrcTag,
+    pub name: String,
+    pub footprint: String,
+    /// Pin numbers of each pin on this footprint, as stated in the package block
+    pub pin_nums: Vec<(String, Vec<PinNum>)>,
+    /// The component's units renumbered for this footprint, once the package is resolved
+    pub units: Vec<Unit>,
+}
+
+impl Package {
+    pub fn new(
+        tag: SrcTag,
+        name: String,
+        footprint: String,
+        pin_nums: Vec<(String, Vec<PinNum>)>,
+    ) -> Package {
+        Package {
+            tag: tag,
+            name: name,
+            footprint: footprint,
+            pin_nums: pin_nums,
+            units: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Component {
     pub tag: SrcTag,
     name: String,
     is_abstract: bool,
+    /// Private components can only be used from the module that declares them
+    pub is_private: bool,
     footprint: Option<String>,
     prefix: Option<String>,
-    default_value: String,
+    default_value: Option<ValueExpr>,
+    fields: Vec<(String, ValueExpr)>,
+    /// Name of the component this one extends, until the inheritance has been resolved
+    pub extends: Option<String>,
+    /// Name of the component this one extended, once the inheritance has been resolved
+    pub inherited_from: Option<String>,
+    pub parameters: Vec<Parameter>,
     pub nets: NetList,
+    /// Pins and nets typed by bundles, which get expanded after all modules are loaded
+    pub bundle_pins: Vec<BundleRef>,
+    pub bundle_nets: Vec<BundleRef>,
     pub instances: Vec<Instance>,
     pub units: Vec<Unit>,
+    pub packages: Vec<Package>,
     pub connects: Vec<(String, String)>,
+    /// Branches of the component's `if` blocks, in the order they were declared
+    pub branches: Vec<Branch>,
+    /// Copies of the component with its branches resolved, keyed by which branches they take,
+    /// for every set of bindings the component is instantiated with
+    pub expansions: BTreeMap<Vec<bool>, Component>,
 }
 
 impl Component {
     pub fn new(tag: SrcTag, name: String, is_abstract: bool) -> Component {
         Component {
             tag: tag,
-            name: name.clone(),
+            name: name,
             is_abstract: is_abstract,
+            is_private: false,
             footprint: None,
             prefix: None,
-            default_value: name,
+            default_value: None,
+            fields: Vec::new(),
+            extends: None,
+            inherited_from: None,
+            parameters: Vec::new(),
             nets: Default::default(),
+            bundle_pins: Vec::new(),
+            bundle_nets: Vec::new(),
             instances: Vec::new(),
             units: vec![Unit::new()],
+            packages: Vec::new(),
             connects: Vec::new(),
+            branches: Vec::new(),
+            expansions: BTreeMap::new(),
         }
     }
 
-    pub fn add_pin(&mut self, pin: Pin) -> error::Result<()> {
+    pub fn add_pin(&mut self, sources: &Sources, pin: Pin, errors: &mut error::Errors) {
         for unit in &mut self.units {
-            unit.pins.add_pin(pin.clone())?;
+            unit.pins.add_pin(sources, pin.clone(), errors);
         }
-
-        Ok(())
     }
 
     pub fn take_pins(&mut self) -> PinMap {
@@ -280,11 +730,6 @@ impl Component {
         &self.units[0].pins
     }
 
-    pub fn pins(&self) -> &PinMap {
-        assert!(!self.is_abstract && !self.has_units());
-        &self.units[0].pins
-    }
-
     pub fn has_units(&self) -> bool {
         self.units.len() > 1
     }
@@ -297,12 +742,247 @@ impl Component {
         &self.name
     }
 
-    pub fn default_value(&self) -> &str {
-        &self.default_value
+    /// Name of the component without the namespace of the module it came from
+    pub fn short_name(&self) -> &str {
+        match self.name.rfind(namespace::SEPARATOR) {
+            Some(index) => &self.name[(index + namespace::SEPARATOR.len())..],
+            None => &self.name,
+        }
+    }
+
+    /// Moves the component into the namespace of the module that declared it
+    pub fn qualify(&mut self, namespace: &str) {
+        self.name = namespace::qualify(namespace, &self.name);
+    }
+
+    /// Value of instances that don't state their own, which defaults to the component's name
+    pub fn default_value(&self) -> ValueExpr {
+        match self.default_value {
+            Some(ref value) => value.clone(),
+            None => ValueExpr::Literal(ParamValue::Str(self.short_name().into())),
+        }
+    }
+
+    pub fn set_default_value(&mut self, value: ValueExpr) {
+        self.default_value = Some(value);
+    }
+
+    /// Inherits everything this component doesn't state itself from the one it extends
+    pub fn inherit(&mut self, sources: &Sources, parent: &Component, errors: &mut error::Errors) {
+        if parent.is_abstract {
+            report!(
+                errors,
+                sources,
+                self.tag,
+                code::COMPONENT,
+                "cannot extend abstract component {}",
+                parent.name
+            );
+        }
+        if self.has_units() || !self.units[0].pins.is_empty() {
+            report!(
+                errors,
+                sources,
+                self.tag,
+                code::COMPONENT,
+                "component {} extends {}, so it cannot declare its own pins",
+                self.name,
+                parent.name
+            );
+        }
+        self.units = parent.units.clone();
+        if self.footprint.is_none() {
+            self.footprint = parent.footprint.clone();
+        }
+        if self.prefix.is_none() {
+            self.prefix = parent.prefix.clone();
+        }
+        if self.default_value.is_none() {
+            self.default_value = Some(parent.default_value());
+        }
+
+        let own_parameters = ::std::mem::replace(&mut self.parameters, parent.parameters.clone());
+        for parameter in own_parameters {
+            let tag = parameter.tag;
+            if let Err(err) = self.add_parameter(parameter) {
+                report!(errors, sources, tag, code::DUPLICATE, "{}", err);
+            }
+        }
+
+        let own_packages = ::std::mem::replace(&mut self.packages, parent.packages.clone());
+        for package in own_packages {
+            match self.packages.iter().position(|p| p.name == package.name) {
+                Some(index) => self.packages[index] = package,
+                None => self.packages.push(package),
+            }
+        }
+
+        let own_fields = ::std::mem::replace(&mut self.fields, parent.fields.clone());
+        for (name, value) in own_fields {
+            match self.fields.iter().position(|field| field.0 == name) {
+                Some(index) => self.fields[index].1 = value,
+                None => self.fields.push((name, value)),
+            }
+        }
+    }
+
+    pub fn add_parameter(&mut self, parameter: Parameter) -> error::Result<()> {
+        if self.find_parameter(&parameter.name).is_some() {
+            err!("duplicate parameter named {}", parameter.name);
+        }
+        self.parameters.push(parameter);
+        Ok(())
+    }
+
+    pub fn find_parameter(&self, name: &str) -> Option<&Parameter> {
+        self.parameters.iter().find(|p| p.name == name)
+    }
+
+    /// Binds the arguments of the given instance of this component to its parameters,
+    /// falling back to parameter defaults. Arguments that reference parameters of the
+    /// parent component are resolved with the parent's bindings. Parameters that can't be
+    /// bound are left out, since this also runs before validation.
+    pub fn bind(&self, instance: &Instance, parent_bindings: &Bindings) -> Bindings {
+        let mut bindings = Bindings::new();
+        for (index, argument) in instance.arguments.iter().enumerate() {
+            let name = match (&argument.name, self.parameters.get(index)) {
+                (&Some(ref name), _) => name.clone(),
+                (&None, Some(parameter)) => parameter.name.clone(),
+                (&None, None) => continue,
+            };
+            if let Some(value) = argument.value.try_resolve(parent_bindings) {
+                bindings.insert(name, value);
+            }
+        }
+        for parameter in &self.parameters {
+            if let Some(ref default) = parameter.default {
+                if !bindings.contains_key(&parameter.name) {
+                    bindings.insert(parameter.name.clone(), default.clone());
+                }
+            }
+        }
+        bindings
+    }
+
+    /// Which of the component's branches the given bindings take
+    pub fn selection(&self, bindings: &Bindings) -> Vec<bool> {
+        self.branches.iter().map(|b| b.holds(bindings)).collect()
+    }
+
+    /// True if the instance at the given index is outside of every branch the selection
+    /// doesn't take
+    pub fn selects_instance(&self, selection: &[bool], index: usize) -> bool {
+        self.branches
+            .iter()
+            .zip(selection)
+            .all(|(branch, &taken)| taken || !branch.instances.contains(&index))
+    }
+
+    /// Copies the component with only the instances, nets and connects of the unconditional
+    /// part and the branches that the selection takes
+    pub fn expand(
+        &self,
+        sources: &Sources,
+        selection: &[bool],
+        errors: &mut error::Errors,
+    ) -> Component {
+        let mut expanded = self.clone();
+        expanded.branches.clear();
+        expanded.expansions.clear();
+        expanded.instances = self.instances
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| self.selects_instance(selection, index))
+            .map(|(_, instance)| instance.clone())
+            .collect();
+        for (branch, _) in self.branches.iter().zip(selection).filter(|b| *b.1) {
+            let nets = branch.nets.iter().map(|net| (net.clone(), branch.nets.tag(net).unwrap()));
+            expanded.nets.extend(sources, nets, errors);
+            expanded.connects.extend(branch.connects.iter().cloned());
+        }
+        expanded
+    }
+
+    /// The expansion of the component for the given bindings, which is the component itself
+    /// if it has no `if` blocks
+    pub fn expansion(&self, bindings: &Bindings) -> &Component {
+        if self.branches.is_empty() {
+            self
+        } else {
+            self.expansions
+                .get(&self.selection(bindings))
+                .expect("every instantiation should have been expanded")
+        }
+    }
+
+    /// Every expansion of the component, or the component itself if it has no `if` blocks
+    pub fn expanded(&self) -> Vec<&Component> {
+        if self.branches.is_empty() {
+            vec![self]
+        } else {
+            self.expansions.values().collect()
+        }
     }
 
-    pub fn set_default_value(&mut self, value: String) {
-        self.default_value = value;
+    /// Instances that at least one expansion of the component includes
+    pub fn expanded_instances(&self) -> Vec<&Instance> {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| {
+                self.branches.is_empty()
+                    || self.expansions
+                        .keys()
+                        .any(|selection| self.selects_instance(selection, index))
+            })
+            .map(|(_, instance)| instance)
+            .collect()
+    }
+
+    /// Resolves the value of an instance of this component
+    pub fn instance_value(
+        &self,
+        instance: &Instance,
+        parent_bindings: &Bindings,
+        bindings: &Bindings,
+    ) -> String {
+        match instance.value() {
+            Some(value) => value.resolve(parent_bindings).to_string(),
+            None => self.default_value().resolve(bindings).to_string(),
+        }
+    }
+
+    /// Resolves the fields of an instance of this component, in the order they were declared
+    pub fn instance_fields(
+        &self,
+        instance: &Instance,
+        parent_bindings: &Bindings,
+        bindings: &Bindings,
+    ) -> Vec<(String, String)> {
+        let mut fields: Vec<(String, String)> = self.fields
+            .iter()
+            .map(|&(ref name, ref value)| (name.clone(), value.resolve(bindings).to_string()))
+            .collect();
+        for &(ref name, ref value) in &instance.fields {
+            let value = value.resolve(parent_bindings).to_string();
+            match fields.iter().position(|field| field.0 == *name) {
+                Some(index) => fields[index].1 = value,
+                None => fields.push((name.clone(), value)),
+            }
+        }
+        fields
+    }
+
+    pub fn fields(&self) -> &[(String, ValueExpr)] {
+        &self.fields
+    }
+
+    pub fn add_field(&mut self, name: String, value: ValueExpr) -> error::Result<()> {
+        if self.fields.iter().any(|field| field.0 == name) {
+            err!("duplicate field named {}", name);
+        }
+        self.fields.push((name, value));
+        Ok(())
     }
 
     pub fn is_abstract(&self) -> bool {
@@ -314,6 +994,132 @@ impl Component {
         self.footprint.as_ref().unwrap()
     }
 
+    pub fn add_package(&mut self, package: Package) -> error::Result<()> {
+        if self.find_package(&package.name).is_some() {
+            err!("duplicate package named {}", package.name);
+        }
+        self.packages.push(package);
+        Ok(())
+    }
+
+    pub fn find_package(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Footprint and units to use for the given package, or for the default package
+    /// when the instance didn't choose one
+    pub fn packaged(&self, package: Option<&String>) -> (&str, &[Unit]) {
+        match package {
+            Some(name) => {
+                let package = self.find_package(name)
+                    .expect("validation should catch this");
+                (&package.footprint, &package.units)
+            }
+            None => (self.footprint(), &self.units),
+        }
+    }
+
+    /// Renumbers the component's pins for each of its packages. Components without a
+    /// footprint of their own default to their first package.
+    pub fn resolve_packages(&mut self, sources: &Sources, errors: &mut error::Errors) {
+        for index in 0..self.packages.len() {
+            let units = self.package_units(sources, &self.packages[index], errors);
+            self.packages[index].units = units;
+        }
+        if self.footprint.is_none() {
+            if let Some(package) = self.packages.first() {
+                self.footprint = Some(package.footprint.clone());
+                self.units = package.units.clone();
+            }
+        }
+    }
+
+    fn package_units(
+        &self,
+        sources: &Sources,
+        package: &Package,
+        errors: &mut error::Errors,
+    ) -> Vec<Unit> {
+        // short names to avoid line wrapping on errors
+        let p = &package.name;
+        let tag = package.tag;
+
+        for (index, &(ref name, _)) in package.pin_nums.iter().enumerate() {
+            if package.pin_nums[0..index].iter().any(|p| p.0 == *name) {
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::DUPLICATE,
+                    "package {} maps pin {} more than once",
+                    p,
+                    name
+                );
+            }
+            if self.units.iter().all(|unit| unit.pins.find_by_name(name).is_none()) {
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "package {} maps pin {}, which doesn't exist",
+                    p,
+                    name
+                );
+            }
+        }
+
+        let mut units = Vec::new();
+        for (index, unit) in self.units.iter().enumerate() {
+            let mut package_unit = Unit::new();
+            for pin in &unit.pins {
+                let nums = match package.pin_nums.iter().find(|p| p.0 == pin.name) {
+                    Some(&(_, ref nums)) => nums,
+                    None => {
+                        report!(
+                            errors,
+                            sources,
+                            tag,
+                            code::COMPONENT,
+                            "package {} is missing pin {}",
+                            p,
+                            pin.name
+                        );
+                        continue;
+                    }
+                };
+                // Unit pins get one pin number per unit, other pins take all of them as pads
+                let copies = self.units
+                    .iter()
+                    .filter(|u| u.pins.find_by_name(&pin.name).is_some())
+                    .count();
+                let nums = if copies == 1 {
+                    nums.clone()
+                } else if nums.len() == copies {
+                    vec![nums[index].clone()]
+                } else {
+                    report!(
+                        errors,
+                        sources,
+                        tag,
+                        code::COMPONENT,
+                        "package {} must give pin {} one pin number for each of its {} units",
+                        p,
+                        pin.name,
+                        copies
+                    );
+                    continue;
+                };
+                let package_pin = Pin::new(pin.tag, pin.name.clone(), pin.typ, nums);
+                package_unit
+                    .pins
+                    .add_pin(sources, package_pin.with_voltage(pin.voltage), errors);
+            }
+            units.push(package_unit);
+        }
+        units
+    }
+
     pub fn set_footprint(&mut self, footprint: String) -> error::Result<()> {
         if self.footprint.is_some() {
             err!("component already has a footprint set");
@@ -335,57 +1141,110 @@ impl Component {
         Ok(())
     }
 
-    pub fn validate_parameters(&self, units: &Sources) -> error::Result<()> {
+    pub fn validate_parameters(&self, sources: &Sources, errors: &mut error::Errors) {
         // short names to avoid line wrapping on errors
         let n = &self.name;
-        let l = || units.locate(self.tag);
+        let tag = self.tag;
 
         if !self.is_abstract {
             if !self.footprint.is_some() || self.footprint.as_ref().unwrap().is_empty() {
-                err!("{}: concrete component {} must specify a footprint", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "concrete component {} must specify a footprint",
+                    n
+                );
             }
             if !self.prefix.is_some() || self.prefix.as_ref().unwrap().is_empty() {
-                err!("{}: concrete component {} must specify a prefix", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "concrete component {} must specify a prefix",
+                    n
+                );
             }
             if !self.instances.is_empty() {
-                err!("{}: concrete component {} cannot have instances", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "concrete component {} cannot have instances",
+                    n
+                );
             }
             if !self.nets.is_empty() {
-                err!("{}: concrete component {} cannot have nets", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "concrete component {} cannot have nets",
+                    n
+                );
             }
         } else {
             if self.footprint.is_some() {
-                err!("{}: abstract component {} cannot have a footprint", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "abstract component {} cannot have a footprint",
+                    n
+                );
             }
             if self.prefix.is_some() {
-                err!("{}: abstract component {} cannot have a prefix", l(), n);
+                report!(
+                    errors,
+                    sources,
+                    tag,
+                    code::COMPONENT,
+                    "abstract component {} cannot have a prefix",
+                    n
+                );
             }
         }
-        Ok(())
     }
 
-    pub fn validate_units(&self, sources: &Sources) -> error::Result<()> {
-        let mut pin_nums = Vec::new();
-        for unit in &self.units {
-            pin_nums.extend((&unit.pins).into_iter().map(|p| p.num.0));
+    pub fn validate_units(&self, sources: &Sources, errors: &mut error::Errors) {
+        if let Some(pin_num) = missing_pin_num(&self.units) {
+            report!(
+                errors,
+                sources,
+                self.tag,
+                code::COMPONENT,
+                "component {} is missing some pins (take a look at pin {})",
+                self.name,
+                pin_num
+            );
         }
-        pin_nums.sort();
-
-        for i in 0..pin_nums.len() {
-            let pin_num = (i + 1) as u32;
-            if pin_nums[i] != pin_num {
-                err!(
-                    "{}: component {} is missing some pins (take a look at pin {})",
-                    sources.locate(self.tag),
+        for package in &self.packages {
+            if let Some(pin_num) = missing_pin_num(&package.units) {
+                report!(
+                    errors,
+                    sources,
+                    package.tag,
+                    code::COMPONENT,
+                    "package {} of component {} is missing some pins (take a look at pin {})",
+                    package.name,
                     self.name,
                     pin_num
                 );
             }
         }
-        Ok(())
     }
 
-    pub fn add_unit_pins(&mut self, mut unit_pins: Vec<UnitPin>) -> error::Result<()> {
+    pub fn add_unit_pins(
+        &mut self,
+        sources: &Sources,
+        mut unit_pins: Vec<UnitPin>,
+        errors: &mut error::Errors,
+    ) -> error::Result<()> {
         if self.has_units() {
             err!(
                 "cannot have multiple unit specifications in component {}",
@@ -418,15 +1277,13 @@ impl Component {
             // Add the non-unit pins to the first unit
             if let Some(pins) = current_pins.take() {
                 for pin in pins.into_iter() {
-                    unit.pins.add_pin(pin)?;
+                    unit.pins.add_pin(sources, pin, errors);
                 }
             }
             for unit_pin in &mut unit_pins {
-                unit.pins.add_pin(Pin::new(
-                    unit_pin.name.clone(),
-                    unit_pin.typ,
-                    unit_pin.nums.remove(0),
-                ))?;
+                let nums = vec![unit_pin.nums.remove(0)];
+                let pin = Pin::new(unit_pin.tag, unit_pin.name.clone(), unit_pin.typ, nums);
+                unit.pins.add_pin(sources, pin.with_voltage(unit_pin.voltage), errors);
             }
             units.push(unit);
         }
@@ -435,3 +1292,26 @@ impl Component {
         Ok(())
     }
 }
+
+/// Finds the first gap in the numbered pins of the given units
+fn missing_pin_num(units: &[Unit]) -> Option<u32> {
+    // Pads like 1A and 1B take up the position of their number, while pins without one
+    // (like the balls of a BGA or an exposed pad named EP) aren't numbered contiguously,
+    // so they're left out of the check
+    let mut pin_nums = Vec::new();
+    for unit in units {
+        for pin in &unit.pins {
+            pin_nums.extend(pin.nums.iter().filter_map(PinNum::position));
+        }
+    }
+
+    pin_nums.sort();
+    pin_nums.dedup();
+    for i in 0..pin_nums.len() {
+        let pin_num = (i + 1) as u32;
+        if pin_nums[i] != pin_num {
+            return Some(pin_num);
+        }
+    }
+    None
+}
@@ -8,82 +8,365 @@
 //
 
 use lalrpop_util::ParseError;
-use std::fs::File;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
+use deps::Dependencies;
+use diagnostic::{self, code, Allow, Diagnostic};
+use erc::ErcMatrix;
 use error;
+use lint;
+use manifest::Manifest;
+use options::Options;
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 mod grammar;
 
 pub mod ast;
+pub mod bundle;
 pub mod component;
+pub mod namespace;
 pub mod source;
 pub mod token;
-mod validator;
+pub mod validator;
 
-use self::ast::{Ast, Tagged};
-use self::component::{Component, Instance, Pin, PinNum};
-use self::source::{Locator, Sources};
+use self::ast::{Ast, ErcRule, Tagged};
+use self::bundle::{Bundle, BundleExpander, BundleRef};
+use self::namespace::Namespaces;
+use self::component::{Bindings, Branch, Component, Condition, Instance, Package, ParamType,
+                      Parameter, Pin, PinNum};
+use self::source::{Locator, Sources, SrcTag};
 use self::validator::Validator;
 
 pub struct ParseResult {
     pub sources: Sources,
     pub components: Vec<Component>,
     pub global_nets: Vec<String>,
+    /// Global nets that declare a voltage, in millivolts, with where they were declared
+    pub global_net_voltages: Vec<(String, SrcTag, u32)>,
+    /// Name of the component to compile
+    pub top: String,
+    /// Problems that don't stop the circuit from compiling, apart from those allowed
+    pub warnings: Vec<Diagnostic>,
+    /// ERC tables with the overrides of the manifest, source files and options
+    pub erc: ErcMatrix,
+    /// Comments that allow warnings, for checks after parsing to respect
+    pub allows: Vec<Allow>,
 }
 
-pub fn parse(file_name: &str) -> error::Result<ParseResult> {
-    let main_file = Path::new(file_name).file_name().unwrap();
+pub fn parse(file_name: &str, options: &Options) -> error::Result<ParseResult> {
     let main_path = Path::new(file_name).parent().unwrap();
+    let manifest = match options.manifest {
+        Some(ref manifest) => Some(manifest.clone()),
+        None => Manifest::find(main_path)?,
+    };
+    let search_path = search_path(options, manifest.as_ref());
+    let mut erc = match manifest {
+        Some(ref manifest) => ErcMatrix::from_manifest(manifest)?,
+        None => ErcMatrix::new(),
+    };
+    let top = match (&options.top, &manifest) {
+        (&Some(ref top), _) => top.clone(),
+        (&None, &Some(Manifest { top: Some(ref top), .. })) => top.clone(),
+        _ => "Main".into(),
+    };
 
     let mut sources = Sources::new();
+    let mut namespaces = Namespaces::new();
 
-    let mut modules_to_require: Vec<PathBuf> = Vec::new();
-    let mut modules_required: Vec<PathBuf> = Vec::new();
-    modules_to_require.push(module_path(&main_path, &main_file).unwrap());
+    // Modules still to load, along with the `require` that asked for them
+    let mut modules_to_require: Vec<(PathBuf, Option<ast::Require>)> = Vec::new();
+    // Canonical paths of the modules loaded so far, so that each is only loaded once
+    let mut modules_required: Vec<(PathBuf, String)> = Vec::new();
+    modules_to_require.push((PathBuf::from(file_name), None));
 
-    let mut global_nets: Vec<String> = Vec::new();
+    let mut global_nets: Vec<(String, SrcTag)> = Vec::new();
+    let mut global_net_voltages: Vec<(String, SrcTag, u32)> = Vec::new();
+    let mut global_bundle_nets: Vec<BundleRef> = Vec::new();
+    let mut bundles: Vec<Bundle> = Vec::new();
     let mut components: Vec<Component> = Vec::new();
-    while let Some(path) = modules_to_require.pop() {
-        if !modules_required.contains(&path) {
-            modules_required.push(path.clone());
-            let source_id = sources.push_source(path.to_str().unwrap().into(), load_file(&path)?);
-            let locator = Locator::new(&sources, source_id);
-            let parse_result = parse_file(&locator, sources.code(source_id))?;
-
-            let path_parent = path.parent().unwrap();
-            for require in parse_result.requires {
-                if let Some(module_path) = module_path(&path_parent, &require.module) {
-                    modules_to_require.push(module_path);
-                } else {
-                    err!(
-                        "{}: cannot find file named \"{}\"",
-                        sources.locate(require.tag),
-                        require.module
-                    );
+    let mut allows: Vec<Allow> = Vec::new();
+    let mut errors = error::Errors::new();
+    while let Some((path, require)) = modules_to_require.pop() {
+        // Namespace of the module doing the requiring, and the one the new module would get
+        let (scope, namespace) = match require {
+            Some(ref require) => {
+                let scope = namespaces.namespace(require.tag.source).to_string();
+                let namespace = match require.namespace {
+                    Some(ref alias) => namespace::qualify(&scope, alias),
+                    None => scope.clone(),
+                };
+                (scope, namespace)
+            }
+            None => (String::new(), String::new()),
+        };
+        let canonical_path = fs::canonicalize(&path)?;
+        let namespace = match modules_required.iter().find(|m| m.0 == canonical_path) {
+            Some(&(_, ref existing)) => existing.clone(),
+            None => {
+                modules_required.push((canonical_path, namespace.clone()));
+                let code = load_file(&path)?;
+                let source_id = sources.push_source(path.to_str().unwrap().into(), code);
+                namespaces.add_module(source_id, namespace.clone());
+                let locator = Locator::new(&sources, source_id);
+                let parse_result = parse_file(&locator, sources.code(source_id))?;
+
+                let path_parent = path.parent().unwrap();
+                for require in parse_result.requires {
+                    match module_path(&path_parent, &search_path, &require.module) {
+                        Ok(module_path) => modules_to_require.push((module_path, Some(require))),
+                        Err(tried) => {
+                            let tried: Vec<String> =
+                                tried.iter().map(|p| p.display().to_string()).collect();
+                            report!(
+                                errors,
+                                &sources,
+                                require.tag,
+                                code::MODULE,
+                                "cannot find file named \"{}\" (tried {})",
+                                require.module,
+                                tried.join(", ")
+                            );
+                        }
+                    }
+                }
+                errors.extend(parse_result.errors);
+                allows.extend(parse_result.allows.into_iter());
+                erc.extend(parse_result.erc);
+                for (net, tag) in parse_result.global_nets {
+                    match global_nets.iter().find(|n| n.0 == net) {
+                        Some(&(_, first_tag)) => {
+                            let message = format!("global net {} is declared more than once", net);
+                            let diagnostic =
+                                Diagnostic::new(&sources, tag, code::DUPLICATE, message)
+                                    .with_label(&sources, first_tag, "first declared here");
+                            errors.push_diagnostic(tag, diagnostic);
+                        }
+                        None => global_nets.push((net, tag)),
+                    }
+                }
+                global_net_voltages.extend(parse_result.global_net_voltages.into_iter());
+                global_bundle_nets.extend(parse_result.global_bundle_nets.into_iter());
+                bundles.extend(parse_result.bundles.into_iter());
+                for mut component in parse_result.components {
+                    component.qualify(&namespace);
+                    components.push(component);
                 }
+                namespace
+            }
+        };
+        if let Some(require) = require {
+            match require.namespace {
+                Some(ref alias) => {
+                    namespaces.add_alias(&sources, require.tag, alias, &namespace)?;
+                }
+                None => namespaces.add_import(&scope, &namespace),
             }
-            global_nets.extend(parse_result.global_nets.into_iter());
-            components.extend(parse_result.components.into_iter());
         }
     }
 
-    Validator::new(&sources, &global_nets, &components).validate()?;
+    // the remaining steps rely on every component having been defined correctly
+    errors.into_result()?;
+
+    namespaces.resolve(&sources, &mut components)?;
+    resolve_extends(&sources, &mut components)?;
+    let mut errors = error::Errors::new();
+    for component in &mut components {
+        component.resolve_packages(&sources, &mut errors);
+    }
+    errors.into_result()?;
+    let mut global_nets: Vec<String> = global_nets.into_iter().map(|n| n.0).collect();
+    BundleExpander::new(&sources, &bundles, &global_bundle_nets)?
+        .expand(&mut global_nets, &mut components)?;
+
+    Dependencies::new(&components, &top).check_cycles(&sources)?;
+    expand_branches(&sources, &mut components, &top)?;
+
+    erc.extend(ErcMatrix::from_options(options));
+    let mut warnings =
+        Validator::new(&sources, &global_nets, &components, &top, &erc).validate()?;
+    let mut lint_levels = match manifest {
+        Some(ref manifest) => manifest.lint_levels()?,
+        None => Vec::new(),
+    };
+    lint_levels.extend(options.lint_levels.iter().cloned());
+    warnings.extend(lint::check(&sources, &components, &global_nets, &top, &lint_levels)?);
+    let warnings = diagnostic::review_warnings(warnings, &allows, options.deny_warnings)?;
 
     Ok(ParseResult {
         sources: sources,
         components: components,
         global_nets: global_nets,
+        global_net_voltages: global_net_voltages,
+        top: top,
+        warnings: warnings,
+        erc: erc,
+        allows: allows,
     })
 }
 
+/// Resolves components that extend others, parents before children
+fn resolve_extends(sources: &Sources, components: &mut Vec<Component>) -> error::Result<()> {
+    let mut errors = error::Errors::new();
+    loop {
+        let mut progress = false;
+        for index in 0..components.len() {
+            let parent_name = match components[index].extends {
+                Some(ref parent_name) => parent_name.clone(),
+                None => continue,
+            };
+            let parent_index = match components.iter().position(|c| c.name() == parent_name) {
+                Some(parent_index) => parent_index,
+                None => {
+                    report!(
+                        errors,
+                        sources,
+                        components[index].tag,
+                        code::COMPONENT,
+                        "cannot find component named {} for {} to extend",
+                        parent_name,
+                        components[index].name()
+                    );
+                    components[index].extends = None;
+                    continue;
+                }
+            };
+            if components[parent_index].extends.is_some() {
+                // wait until the parent has inherited from its own parent
+                continue;
+            }
+            let (child, parent) = if index < parent_index {
+                let (left, right) = components.split_at_mut(parent_index);
+                (&mut left[index], &right[0])
+            } else {
+                let (left, right) = components.split_at_mut(index);
+                (&mut right[0], &left[parent_index])
+            };
+            child.inherit(sources, parent, &mut errors);
+            child.inherited_from = child.extends.take();
+            progress = true;
+        }
+        if !progress {
+            break;
+        }
+    }
+    errors.into_result()?;
+    if let Some(index) = components.iter().position(|c| c.extends.is_some()) {
+        let diagnostic = describe_extends_cycle(sources, components, index);
+        return Err(error::ErrorKind::Diagnostic(diagnostic).into());
+    }
+    Ok(())
+}
+
+/// Expands the `if` blocks of abstract components for every set of bindings they're
+/// instantiated with, walking down from the top component. Components that nothing
+/// instantiates are expanded as though they were instantiated without arguments.
+fn expand_branches(
+    sources: &Sources,
+    components: &mut [Component],
+    top: &str,
+) -> error::Result<()> {
+    let mut visited: BTreeSet<(usize, Bindings)> = BTreeSet::new();
+    let mut selections: BTreeSet<(usize, Vec<bool>)> = BTreeSet::new();
+    let top_index = components.iter().position(|c| c.name() == top);
+    for root in top_index.into_iter().chain(0..components.len()) {
+        let component = &components[root];
+        if !component.is_abstract() || visited.iter().any(|v| v.0 == root) {
+            continue;
+        }
+        let instance = Instance::new(component.tag, component.name().into());
+        let mut to_visit = vec![(root, component.bind(&instance, &Bindings::new()))];
+        while let Some((index, bindings)) = to_visit.pop() {
+            if visited.contains(&(index, bindings.clone())) {
+                continue;
+            }
+            let component = &components[index];
+            let selection = component.selection(&bindings);
+            for (instance_index, instance) in component.instances.iter().enumerate() {
+                if !component.selects_instance(&selection, instance_index) {
+                    continue;
+                }
+                if let Some(child) = components.iter().position(|c| c.name() == instance.name) {
+                    if components[child].is_abstract() {
+                        to_visit.push((child, components[child].bind(instance, &bindings)));
+                    }
+                }
+            }
+            selections.insert((index, selection));
+            visited.insert((index, bindings));
+        }
+    }
+
+    let mut errors = error::Errors::new();
+    for (index, selection) in selections {
+        if !components[index].branches.is_empty() {
+            let expanded = components[index].expand(sources, &selection, &mut errors);
+            components[index].expansions.insert(selection, expanded);
+        }
+    }
+    errors.into_result()
+}
+
+/// Describes the cycle of extends that the given unresolved component leads into
+fn describe_extends_cycle(sources: &Sources, components: &[Component], index: usize) -> Diagnostic {
+    let parent_of = |index: usize| {
+        let parent_name = components[index].extends.as_ref().unwrap();
+        components.iter().position(|c| c.name() == parent_name).unwrap()
+    };
+    let mut chain = Vec::new();
+    let mut index = index;
+    while !chain.contains(&index) {
+        chain.push(index);
+        index = parent_of(index);
+    }
+    let start = chain.iter().position(|&i| i == index).unwrap();
+    let cycle = &chain[start..];
+
+    let steps: Vec<String> = cycle
+        .iter()
+        .map(|&i| {
+            format!(
+                "{} extends {} at {}",
+                components[i].name(),
+                components[parent_of(i)].name(),
+                sources.locate(components[i].tag)
+            )
+        })
+        .collect();
+    let first = &components[cycle[0]];
+    let message = format!("component {} extends itself: {}", first.name(), steps.join(", "));
+    let mut diagnostic = Diagnostic::new(sources, first.tag, code::COMPONENT, message);
+    for &i in &cycle[1..] {
+        let label = format!(
+            "{} extends {} here",
+            components[i].name(),
+            components[parent_of(i)].name()
+        );
+        diagnostic = diagnostic.with_label(sources, components[i].tag, &label);
+    }
+    diagnostic
+}
+
 #[derive(Default)]
 pub struct ParseFileResult {
     pub requires: Vec<ast::Require>,
+    pub bundles: Vec<Bundle>,
     pub components: Vec<Component>,
-    pub global_nets: Vec<String>,
+    /// Names of global nets, along with where they're declared
+    pub global_nets: Vec<(String, SrcTag)>,
+    pub global_net_voltages: Vec<(String, SrcTag, u32)>,
+    pub global_bundle_nets: Vec<BundleRef>,
+    /// Errors in component definitions, which don't stop the rest of the file being considered
+    pub errors: error::Errors,
+    /// `netmuncher:allow` comments in the file
+    pub allows: Vec<Allow>,
+    /// Overrides from the file's `erc` blocks
+    pub erc: ErcMatrix,
+    /// Index of the component's branch that the statements being considered belong to
+    branch: Option<usize>,
 }
 
 impl ParseFileResult {
@@ -97,52 +380,109 @@ impl ParseFileResult {
                 self.requires.push(require);
             }
             Ast::Nets(global_nets) => {
-                self.global_nets.extend(global_nets.nets.into_iter());
+                if let Some(bundle) = global_nets.bundle {
+                    let tag = global_nets.tag;
+                    self.global_bundle_nets.extend(
+                        global_nets
+                            .nets
+                            .into_iter()
+                            .map(|net| BundleRef::new(tag, net, bundle.clone())),
+                    );
+                } else {
+                    let tag = global_nets.tag;
+                    if let Some(voltage) = global_nets.voltage {
+                        self.global_net_voltages.extend(
+                            global_nets.nets.iter().map(|net| (net.clone(), tag, voltage)),
+                        );
+                    }
+                    self.global_nets.extend(global_nets.nets.into_iter().map(|net| (net, tag)));
+                }
+            }
+            Ast::BundleDef(def) => {
+                self.bundles.push(Bundle::new(def.tag, def.name, def.members));
             }
             Ast::ComponentDef(component_def) => {
-                let offset = component_def.tag.offset;
-                let name = component_def.name.clone();
-                self.consider_component(locator, component_def)
-                    .map_err(|err| {
-                        err.chain_err(|| {
-                            error::ErrorKind::NetmuncherError(format!(
-                                "{}: error in component {}",
-                                locator.locate(offset),
-                                name
-                            ))
-                        })
-                    })?;
+                self.consider_component(locator, component_def);
             }
+            Ast::Erc(block) => for rule in block.rules {
+                match rule {
+                    ErcRule::Connection(first, second, level) => {
+                        self.erc.connections.push((first, second, level))
+                    }
+                    ErcRule::Mapping(first, second, level) => {
+                        self.erc.mappings.push((first, second, level))
+                    }
+                    ErcRule::Net(check, level) => self.erc.net_checks.push((check, level)),
+                }
+            },
             _ => unreachable!("grammar should not allow this to be reached"),
         }
         Ok(())
     }
 
-    fn consider_component(
-        &mut self,
-        locator: &Locator,
-        def: ast::ComponentDef,
-    ) -> error::Result<()> {
+    /// Builds a component from its definition, collecting the errors in each of its
+    /// statements rather than stopping at the first
+    fn consider_component(&mut self, locator: &Locator, def: ast::ComponentDef) {
+        let sources = locator.sources();
+        let located = |err: error::Error, tag: SrcTag| -> error::Error {
+            Diagnostic::new(sources, tag, code::COMPONENT, err.to_string()).into()
+        };
+
+        let mut errors = error::Errors::new();
         let mut component = Component::new(def.tag, def.name, def.is_abstract);
+        component.extends = def.extends;
+        component.is_private = def.is_private;
+        for parameter in def.parameters {
+            let tag = parameter.tag;
+            if let Err(err) = self.consider_parameter(&mut component, parameter) {
+                errors.push(Some(tag), located(err, tag));
+            }
+        }
         for param in def.params {
             let tag = param.tag();
-            self.consider_component_param(&mut component, param)
-                .map_err(|err| {
-                    error::ErrorKind::NetmuncherError(format!(
-                        "{}: {}",
-                        locator.locate(tag.offset),
-                        err
-                    ))
-                })?;
+            let result = self.consider_component_param(sources, &mut component, param, &mut errors);
+            if let Err(err) = result {
+                errors.push(Some(tag), located(err, tag));
+                // an error inside of an if block leaves its branch behind
+                self.branch = None;
+            }
         }
+        let message = format!("error in component {}", component.name());
+        let diagnostic = Diagnostic::new(sources, component.tag, code::COMPONENT, message);
+        self.errors
+            .extend(errors.chain_err(|| error::ErrorKind::Diagnostic(diagnostic.clone())));
         self.components.push(component);
-        Ok(())
+    }
+
+    fn consider_parameter(
+        &mut self,
+        component: &mut Component,
+        def: ast::ParameterDef,
+    ) -> error::Result<()> {
+        let typ = match (def.typ, def.default.as_ref()) {
+            (Some(typ), Some(default)) => {
+                if typ != default.typ() {
+                    err!(
+                        "default value for parameter {} must be a {}",
+                        def.name,
+                        typ
+                    );
+                }
+                typ
+            }
+            (Some(typ), None) => typ,
+            (None, Some(default)) => default.typ(),
+            (None, None) => ParamType::String,
+        };
+        component.add_parameter(Parameter::new(def.tag, def.name, typ, def.default))
     }
 
     fn consider_component_param(
         &mut self,
+        sources: &Sources,
         component: &mut Component,
         param: Ast,
+        errors: &mut error::Errors,
     ) -> error::Result<()> {
         match param {
             Ast::AbstractPins(abstract_pins) => {
@@ -150,27 +490,47 @@ impl ParseFileResult {
                     err!("concrete components must state pin numbers for pins");
                 }
                 for pin in abstract_pins {
-                    let num = (component.abstract_pins().len() + 1) as u32;
-                    component.add_pin(Pin::new(pin.name, pin.typ, PinNum(num)))?;
+                    let num = component.abstract_pins().len() + 1;
+                    let nums = vec![PinNum::from_index(num)];
+                    component.add_pin(sources, Pin::new(pin.tag, pin.name, pin.typ, nums), errors);
+                }
+            }
+            Ast::BundlePins(bundle_pins) => {
+                if !component.is_abstract() {
+                    err!("concrete components cannot have bundle pins");
                 }
+                component.bundle_pins.extend(bundle_pins.into_iter());
             }
             Ast::ConcretePins(concrete_pins) => {
                 if component.is_abstract() {
                     err!("abstract components shouldn't state pin numbers for pins");
                 }
                 for pin in concrete_pins {
-                    component.add_pin(Pin::new(pin.name, pin.typ, pin.num))?;
+                    let voltage = pin.voltage;
+                    let pin = Pin::new(pin.tag, pin.name, pin.typ, pin.nums).with_voltage(voltage);
+                    component.add_pin(sources, pin, errors);
                 }
             }
             Ast::Connect(connect) => {
                 if !component.is_abstract() {
                     err!("concrete components cannot have internal connects");
                 }
+                check_unrolled(&connect.left)?;
+                check_unrolled(&connect.right)?;
                 if connect.left.len() != connect.right.len() {
                     err!("must connect the same number of pins/nets on the left and right");
                 }
                 let zipped = connect.left.into_iter().zip(connect.right.into_iter());
-                component.connects.extend(zipped);
+                match self.branch {
+                    Some(index) => component.branches[index].connects.extend(zipped),
+                    None => component.connects.extend(zipped),
+                }
+            }
+            Ast::Field(field) => {
+                if component.is_abstract() {
+                    err!("abstract components shouldn't have fields");
+                }
+                component.add_field(field.name, field.value)?;
             }
             Ast::Footprint(footprint) => {
                 if component.is_abstract() {
@@ -178,6 +538,28 @@ impl ParseFileResult {
                 }
                 component.set_footprint(footprint.footprint)?;
             }
+            Ast::For(for_loop) => {
+                if !component.is_abstract() {
+                    err!("concrete components cannot have for blocks");
+                }
+                for value in for_loop.range {
+                    for param in &for_loop.body {
+                        for param in param.clone().substitute(&for_loop.var, value) {
+                            self.consider_component_param(sources, component, param, errors)?;
+                        }
+                    }
+                }
+            }
+            Ast::If(if_block) => {
+                if !component.is_abstract() {
+                    err!("concrete components cannot have if blocks");
+                }
+                let else_condition = if_block.condition.negate();
+                let tag = if_block.tag;
+                let (body, else_body) = (if_block.body, if_block.else_body);
+                self.consider_branch(sources, component, tag, if_block.condition, body, errors)?;
+                self.consider_branch(sources, component, tag, else_condition, else_body, errors)?;
+            }
             Ast::InstanceDef(instance_def) => {
                 self.consider_instance(component, instance_def)?;
             }
@@ -185,9 +567,36 @@ impl ParseFileResult {
                 if !component.is_abstract() {
                     err!("concrete components shouldn't have nets");
                 }
-                for net in nets.nets {
-                    component.nets.add_net(net)?;
+                check_unrolled(&nets.nets)?;
+                if nets.voltage.is_some() {
+                    err!("only global nets can have a voltage");
+                }
+                let (net_list, bundle_nets) = match self.branch {
+                    Some(index) => {
+                        let branch = &mut component.branches[index];
+                        (&mut branch.nets, &mut branch.bundle_nets)
+                    }
+                    None => (&mut component.nets, &mut component.bundle_nets),
+                };
+                if let Some(bundle) = nets.bundle {
+                    for net in nets.nets {
+                        bundle_nets.push(BundleRef::new(nets.tag, net, bundle.clone()));
+                    }
+                } else {
+                    for net in nets.nets {
+                        net_list.add_net(sources, net, nets.tag, errors);
+                    }
+                }
+            }
+            Ast::PackageDef(def) => {
+                if component.is_abstract() {
+                    err!("abstract components shouldn't have packages");
                 }
+                if def.footprints.len() != 1 {
+                    err!("package {} must specify exactly one footprint", def.name);
+                }
+                let footprint = def.footprints.into_iter().next().unwrap().footprint;
+                component.add_package(Package::new(def.tag, def.name, footprint, def.pins))?;
             }
             Ast::Prefix(prefix) => {
                 if component.is_abstract() {
@@ -199,13 +608,65 @@ impl ParseFileResult {
                 component.set_default_value(value.value);
             }
             Ast::Unit(unit) => {
-                component.add_unit_pins(unit.pins)?;
+                component.add_unit_pins(sources, unit.pins, errors)?;
             }
             _ => unreachable!("grammar should not allow this to be reached"),
         }
         Ok(())
     }
 
+    /// Adds a branch of an `if` block to the component, inside the branch currently being
+    /// considered if there is one
+    fn consider_branch(
+        &mut self,
+        sources: &Sources,
+        component: &mut Component,
+        tag: SrcTag,
+        condition: Condition,
+        body: Vec<Ast>,
+        errors: &mut error::Errors,
+    ) -> error::Result<()> {
+        if body.is_empty() {
+            return Ok(());
+        }
+        let outer = self.branch;
+        let mut conditions = match outer {
+            Some(index) => component.branches[index].conditions.clone(),
+            None => Vec::new(),
+        };
+        conditions.push(condition);
+        let first_instance = component.instances.len();
+        component.branches.push(Branch::new(tag, conditions, first_instance));
+        let index = component.branches.len() - 1;
+        self.branch = Some(index);
+        for param in body {
+            self.consider_conditional_param(sources, component, param, errors)?;
+        }
+        component.branches[index].instances.end = component.instances.len();
+        self.branch = outer;
+        Ok(())
+    }
+
+    fn consider_conditional_param(
+        &mut self,
+        sources: &Sources,
+        component: &mut Component,
+        param: Ast,
+        errors: &mut error::Errors,
+    ) -> error::Result<()> {
+        match param {
+            Ast::Connect(_) | Ast::For(_) | Ast::If(_) | Ast::InstanceDef(_) | Ast::Nets(_) => {
+                self.consider_component_param(sources, component, param, errors)
+            }
+            _ => {
+                err!(
+                    "if blocks can only contain instances, nets, connects and other for or if \
+                     blocks"
+                );
+            }
+        }
+    }
+
     fn consider_instance(
         &mut self,
         component: &mut Component,
@@ -215,6 +676,17 @@ impl ParseFileResult {
             err!("concrete components cannot have instances");
         }
         let mut instance = Instance::new(def.tag, def.name);
+        if let Some(label) = def.label {
+            check_unrolled(&[label.clone()])?;
+            instance.label = Some(label);
+        }
+        instance.arguments = def.arguments;
+        if let Some(count) = def.count {
+            if count < 1 {
+                err!("instance arrays must have at least one instance");
+            }
+            instance.array = Some(count);
+        }
         for param in def.parameters {
             match param {
                 Ast::Value(value) => {
@@ -223,10 +695,20 @@ impl ParseFileResult {
                     }
                     instance.value = Some(value.value);
                 }
+                Ast::PackageRef(package) => {
+                    if instance.package.is_some() {
+                        err!("multiple packages specified for instance");
+                    }
+                    instance.package = Some(package.package);
+                }
+                Ast::Field(field) => {
+                    if instance.fields.iter().any(|f| f.0 == field.name) {
+                        err!("multiple values specified for field {} of instance", field.name);
+                    }
+                    instance.fields.push((field.name, field.value));
+                }
                 Ast::ConnectionMap(conn_map) => {
-                    instance
-                        .connections
-                        .extend(conn_map.connections.into_iter());
+                    self.consider_connection_map(&mut instance, conn_map)?;
                 }
                 _ => unreachable!("grammar should not allow this to be reached"),
             }
@@ -234,48 +716,133 @@ impl ParseFileResult {
         component.instances.push(instance);
         Ok(())
     }
+
+    fn consider_connection_map(
+        &mut self,
+        instance: &mut Instance,
+        map: ast::ConnectionMap,
+    ) -> error::Result<()> {
+        check_unrolled(&map.left)?;
+        check_unrolled(&map.right)?;
+        let (left, right) = (map.left, map.right);
+        let copies = instance.copies();
+        if right.len() == 1 {
+            let zipped = left.iter().cloned().zip(left.iter().map(|_| right[0].clone()));
+            instance.connections.extend(zipped);
+        } else if right.len() == left.len() {
+            instance
+                .connections
+                .extend(left.into_iter().zip(right.into_iter()));
+        } else if instance.array.is_some() && right.len() == left.len() * copies {
+            // Spread the connections across the copies, one left-hand group per copy
+            for (index, pin) in left.iter().enumerate() {
+                let nets = (0..copies)
+                    .map(|copy| right[copy * left.len() + index].clone())
+                    .collect();
+                instance.spread_connections.push((pin.clone(), nets));
+            }
+        } else if instance.array.is_some() {
+            let mut widths = vec![1, left.len(), left.len() * copies];
+            widths.dedup();
+            let widths: Vec<String> = widths.iter().map(|w| w.to_string()).collect();
+            err!(
+                "expected {} connections on the right, but found {}",
+                widths.join(" or "),
+                right.len()
+            );
+        } else {
+            err!(
+                "expected {} connections on the right, but found {}",
+                left.len(),
+                right.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Errors if any of the names are still indexed by a for loop variable after unrolling
+fn check_unrolled(names: &[String]) -> error::Result<()> {
+    for name in names {
+        if let Some(start) = name.find('[') {
+            err!(
+                "{} is indexed by {}, which isn't a for loop variable",
+                name,
+                &name[(start + 1)..(name.len() - 1)]
+            );
+        }
+    }
+    Ok(())
 }
 
 fn parse_file(locator: &Locator, source: &str) -> error::Result<ParseFileResult> {
-    let tokens = token::tokenize(locator, source)?;
+    let (tokens, allows) = token::tokenize(locator, source)?;
     let trees = grammar::parse_Source(&locator, tokens.into_iter()).map_err(|e| match e {
-        ParseError::InvalidToken { location } => error::ErrorKind::NetmuncherError(format!(
-            "{}: invalid token",
-            locator.locate(location)
-        )).into(),
+        ParseError::InvalidToken { location } => {
+            let message = "invalid token".into();
+            Diagnostic::new(locator.sources(), locator.tag(location), code::SYNTAX, message).into()
+        }
         ParseError::UnrecognizedToken { token, expected } => match token {
-            Some((location, token, _)) => error::ErrorKind::NetmuncherError(format!(
-                "{}: unexpected token \"{}\". Expected one of: {}",
-                locator.locate(location),
-                token,
-                expected.join(", ")
-            )).into(),
-            None => error::ErrorKind::NetmuncherError(
-                format!("{}: unexpected end of file", locator.name()).into(),
-            ).into(),
+            Some((location, token, end)) => {
+                let message = format!(
+                    "unexpected token \"{}\". Expected one of: {}",
+                    token,
+                    expected.join(", ")
+                );
+                let tag = locator.span(location, end);
+                Diagnostic::new(locator.sources(), tag, code::SYNTAX, message).into()
+            }
+            None => {
+                let message = "unexpected end of file".into();
+                let tag = locator.tag(source.len());
+                Diagnostic::new(locator.sources(), tag, code::SYNTAX, message).into()
+            }
         },
-        ParseError::ExtraToken { token } => error::ErrorKind::NetmuncherError(format!(
-            "{}: extra token {}",
-            locator.locate(token.0),
-            token.1
-        )).into(),
+        ParseError::ExtraToken { token } => {
+            let message = format!("extra token {}", token.1);
+            let tag = locator.span(token.0, token.2);
+            Diagnostic::new(locator.sources(), tag, code::SYNTAX, message).into()
+        }
         ParseError::User { error } => error,
     })?;
 
     let mut result = ParseFileResult::new();
+    result.allows = allows;
     for tree in trees {
         result.consider_tree(locator, tree)?;
     }
     Ok(result)
 }
 
-fn module_path<P: AsRef<Path>>(main_path: &Path, module_name: P) -> Option<PathBuf> {
-    let path = main_path.join(module_name);
-    if path.is_file() {
-        Some(path)
-    } else {
-        None
+/// Directories to look for required modules in when they aren't next to the requiring module:
+/// the library paths given in the options, then `NETMUNCHER_PATH`, then the manifest's
+fn search_path(options: &Options, manifest: Option<&Manifest>) -> Vec<PathBuf> {
+    let mut paths = options.lib_paths.clone();
+    if let Some(env_paths) = env::var_os("NETMUNCHER_PATH") {
+        paths.extend(env::split_paths(&env_paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+    if let Some(manifest) = manifest {
+        paths.extend(manifest.lib_paths());
+    }
+    paths
+}
+
+/// Finds a required module relative to the requiring module, and then in the search path.
+/// Returns every location that was tried if it can't be found.
+fn module_path<P: AsRef<Path>>(
+    requiring_path: &Path,
+    search_path: &[PathBuf],
+    module_name: P,
+) -> Result<PathBuf, Vec<PathBuf>> {
+    let mut tried = Vec::new();
+    for dir in ::std::iter::once(requiring_path).chain(search_path.iter().map(|p| p.as_path())) {
+        let path = dir.join(module_name.as_ref());
+        if path.is_file() {
+            return Ok(path);
+        }
+        tried.push(path);
     }
+    Err(tried)
 }
 
 fn load_file<P: AsRef<Path>>(file_name: P) -> error::Result<String> {
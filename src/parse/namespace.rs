@@ -0,0 +1,188 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Modules required with `require "file.nm" as name;` get their own namespace, so that
+//! libraries can define components with the same names without colliding. Their components
+//! are renamed to `name::Component`, and references like `ti::LM358` are resolved to those
+//! qualified names once every module has been loaded. Modules required without a name share
+//! the namespace of the module that required them.
+
+use std::collections::BTreeMap;
+
+use diagnostic::code;
+use error;
+use parse::component::Component;
+use parse::source::{Sources, SrcTag};
+
+pub const SEPARATOR: &str = "::";
+
+/// Qualifies a name with a namespace, where the empty namespace is the top level
+pub fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.into()
+    } else {
+        format!("{}{}{}", namespace, SEPARATOR, name)
+    }
+}
+
+/// Names that can be used from within a namespace to reach components of other namespaces
+#[derive(Debug, Default)]
+struct Scope {
+    /// Namespaces of modules required with `as`, by the name they were given
+    aliases: BTreeMap<String, String>,
+    /// Namespaces of modules that were required without a name after already being loaded
+    /// into some other namespace
+    imports: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Namespaces {
+    scopes: BTreeMap<String, Scope>,
+    /// Namespace of each source file
+    modules: BTreeMap<usize, String>,
+}
+
+impl Namespaces {
+    pub fn new() -> Namespaces {
+        Default::default()
+    }
+
+    pub fn namespace(&self, source: usize) -> &str {
+        &self.modules[&source]
+    }
+
+    pub fn add_module(&mut self, source: usize, namespace: String) {
+        self.scopes.entry(namespace.clone()).or_default();
+        self.modules.insert(source, namespace);
+    }
+
+    pub fn add_alias(
+        &mut self,
+        sources: &Sources,
+        tag: SrcTag,
+        alias: &str,
+        target: &str,
+    ) -> error::Result<()> {
+        let scope = self.scopes.entry(self.modules[&tag.source].clone()).or_default();
+        if let Some(existing) = scope.aliases.get(alias) {
+            if existing != target {
+                err_at!(
+                    sources,
+                    tag,
+                    code::NAMESPACE,
+                    "namespace {} is already used for another module",
+                    alias
+                );
+            }
+        }
+        scope.aliases.insert(alias.into(), target.into());
+        Ok(())
+    }
+
+    pub fn add_import(&mut self, namespace: &str, target: &str) {
+        let scope = self.scopes.entry(namespace.into()).or_default();
+        if namespace != target && !scope.imports.iter().any(|i| i == target) {
+            scope.imports.push(target.into());
+        }
+    }
+
+    /// Replaces the component names used by instances and `extends` with qualified names
+    pub fn resolve(&self, sources: &Sources, components: &mut Vec<Component>) -> error::Result<()> {
+        let visible: BTreeMap<String, (bool, usize)> = components
+            .iter()
+            .map(|c| (c.name().into(), (c.is_private, c.tag.source)))
+            .collect();
+        let resolver = Resolver {
+            namespaces: self,
+            sources: sources,
+            visible: visible,
+        };
+        for component in components.iter_mut() {
+            let tag = component.tag;
+            if let Some(parent) = component.extends.take() {
+                component.extends = Some(resolver.resolve(tag, &parent)?);
+            }
+            for instance in &mut component.instances {
+                instance.name = resolver.resolve(instance.tag, &instance.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Resolver<'a> {
+    namespaces: &'a Namespaces,
+    sources: &'a Sources,
+    /// Whether each component is private, and the source it was declared in
+    visible: BTreeMap<String, (bool, usize)>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Resolves a component name used at the given location. Names that can't be found are
+    /// still qualified, so that validation reports them rather than matching a component in
+    /// some other namespace.
+    fn resolve(&self, tag: SrcTag, name: &str) -> error::Result<String> {
+        let namespace = self.namespaces.namespace(tag.source);
+        if let Some(resolved) = self.resolve_in(tag, namespace, name, true)? {
+            return Ok(resolved);
+        }
+        let scope = &self.namespaces.scopes[namespace];
+        for import in &scope.imports {
+            if let Some(resolved) = self.resolve_in(tag, import, name, false)? {
+                return Ok(resolved);
+            }
+        }
+        Ok(qualify(namespace, name))
+    }
+
+    fn resolve_in(
+        &self,
+        tag: SrcTag,
+        namespace: &str,
+        name: &str,
+        local: bool,
+    ) -> error::Result<Option<String>> {
+        if let Some(index) = name.find(SEPARATOR) {
+            let (alias, rest) = (&name[0..index], &name[(index + SEPARATOR.len())..]);
+            let target = match self.namespaces.scopes[namespace].aliases.get(alias) {
+                Some(target) => target,
+                None => {
+                    err_at!(
+                        self.sources,
+                        tag,
+                        code::NAMESPACE,
+                        "cannot find namespace {} for {}",
+                        alias,
+                        name
+                    );
+                }
+            };
+            return match self.resolve_in(tag, target, rest, false)? {
+                Some(resolved) => Ok(Some(resolved)),
+                None => Ok(Some(qualify(target, rest))),
+            };
+        }
+
+        let qualified = qualify(namespace, name);
+        match self.visible.get(&qualified) {
+            Some(&(true, source)) if !local || source != tag.source => {
+                err_at!(
+                    self.sources,
+                    tag,
+                    code::NAMESPACE,
+                    "component {} is private to {}",
+                    name,
+                    self.sources.name(source)
+                );
+            }
+            Some(_) => Ok(Some(qualified)),
+            None => Ok(None),
+        }
+    }
+}
@@ -7,37 +7,54 @@
 // copied, modified, or distributed except according to those terms.
 //
 
-/// Represents the character offset in the program code where something is located
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Represents the span of character offsets in the program code where something is located
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
 pub struct SrcTag {
     pub source: usize,
+    /// Offset of the start of the span
     pub offset: usize,
+    /// Offset just past the end of the span, which is the same as the start for a point
+    pub end: usize,
 }
 
 impl SrcTag {
     pub fn new(source: usize, offset: usize) -> SrcTag {
+        SrcTag::span(source, offset, offset)
+    }
+
+    pub fn span(source: usize, offset: usize, end: usize) -> SrcTag {
         SrcTag {
             source: source,
             offset: offset,
+            end: end,
         }
     }
 
     /// Returns the (row, column) of this tag in the given program text
     pub fn row_col(&self, program: &str) -> (usize, usize) {
-        let mut row: usize = 1;
-        let mut col: usize = 1;
-
-        for i in 0..self.offset {
-            if &program[i..i + 1] == "\n" {
-                row += 1;
-                col = 1;
-            } else {
-                col += 1;
-            }
-        }
+        row_col(program, self.offset)
+    }
+
+    /// Returns the (row, column) just past the end of this tag in the given program text
+    pub fn end_row_col(&self, program: &str) -> (usize, usize) {
+        row_col(program, self.end)
+    }
+}
+
+fn row_col(program: &str, offset: usize) -> (usize, usize) {
+    let mut row: usize = 1;
+    let mut col: usize = 1;
 
-        (row, col)
+    for i in 0..offset {
+        if &program[i..i + 1] == "\n" {
+            row += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
     }
+
+    (row, col)
 }
 
 pub trait SrcTagged {
@@ -113,12 +130,15 @@ impl<'a> Locator<'a> {
         self.sources.name(self.current_source)
     }
 
-    pub fn locate(&self, offset: usize) -> String {
+    pub fn sources(&self) -> &Sources {
         self.sources
-            .locate(SrcTag::new(self.current_source, offset))
     }
 
     pub fn tag(&self, offset: usize) -> SrcTag {
         SrcTag::new(self.current_source, offset)
     }
+
+    pub fn span(&self, offset: usize, end: usize) -> SrcTag {
+        SrcTag::span(self.current_source, offset, end)
+    }
 }
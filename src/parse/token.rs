@@ -10,8 +10,10 @@
 use std::fmt;
 use std::str::FromStr;
 
-use error::{self, ErrorKind};
-use parse::source::Locator;
+use error;
+use diagnostic::{code, Allow, Diagnostic};
+use parse::component::format_voltage;
+use parse::source::{Locator, SrcTag};
 
 use lalrpop_util::ParseError;
 
@@ -24,27 +26,44 @@ pub enum Tok {
     LBracket,
     RBracket,
     Equals,
+    EqualsEquals,
+    NotEquals,
+    Dot,
     DotDot,
     Comma,
     Colon,
+    ColonColon,
     Semicolon,
     Num(u32),
+    /// A voltage in millivolts, written like `3.3V`
+    Voltage(u32),
     Quote(String),
     Symbol(String),
     KeywordAbstract,
+    KeywordAs,
     KeywordBidirectional,
+    KeywordBundle,
     KeywordComponent,
     KeywordConnect,
+    KeywordElse,
+    KeywordErc,
+    KeywordExtends,
+    KeywordField,
     KeywordFootprint,
+    KeywordFor,
     KeywordGlobal,
+    KeywordIf,
+    KeywordIn,
     KeywordInput,
     KeywordNet,
     KeywordNoConnect,
     KeywordOutput,
+    KeywordPackage,
     KeywordPassive,
     KeywordPin,
     KeywordPowerIn,
     KeywordPowerOut,
+    KeywordPrivate,
     KeywordPrefix,
     KeywordRequire,
     KeywordTristate,
@@ -62,27 +81,43 @@ impl fmt::Display for Tok {
             Tok::LBracket => write!(f, "["),
             Tok::RBracket => write!(f, "]"),
             Tok::Equals => write!(f, "="),
+            Tok::EqualsEquals => write!(f, "=="),
+            Tok::NotEquals => write!(f, "!="),
+            Tok::Dot => write!(f, "."),
             Tok::DotDot => write!(f, ".."),
             Tok::Comma => write!(f, ","),
             Tok::Colon => write!(f, ":"),
+            Tok::ColonColon => write!(f, "::"),
             Tok::Semicolon => write!(f, ";"),
             Tok::Num(num) => write!(f, "{}", num),
+            Tok::Voltage(millivolts) => write!(f, "{}", format_voltage(millivolts)),
             Tok::Quote(_) => write!(f, "\""),
             Tok::Symbol(ref sym) => write!(f, "{}", sym),
             Tok::KeywordAbstract => write!(f, "abstract"),
+            Tok::KeywordAs => write!(f, "as"),
             Tok::KeywordBidirectional => write!(f, "bidirectional"),
+            Tok::KeywordBundle => write!(f, "bundle"),
             Tok::KeywordComponent => write!(f, "component"),
             Tok::KeywordConnect => write!(f, "connect"),
+            Tok::KeywordElse => write!(f, "else"),
+            Tok::KeywordErc => write!(f, "erc"),
+            Tok::KeywordExtends => write!(f, "extends"),
+            Tok::KeywordField => write!(f, "field"),
             Tok::KeywordFootprint => write!(f, "footprint"),
+            Tok::KeywordFor => write!(f, "for"),
             Tok::KeywordGlobal => write!(f, "global"),
+            Tok::KeywordIf => write!(f, "if"),
+            Tok::KeywordIn => write!(f, "in"),
             Tok::KeywordInput => write!(f, "input"),
             Tok::KeywordNet => write!(f, "net"),
             Tok::KeywordNoConnect => write!(f, "noconnect"),
             Tok::KeywordOutput => write!(f, "output"),
+            Tok::KeywordPackage => write!(f, "package"),
             Tok::KeywordPassive => write!(f, "passive"),
             Tok::KeywordPin => write!(f, "pin"),
             Tok::KeywordPowerIn => write!(f, "power_in"),
             Tok::KeywordPowerOut => write!(f, "power_out"),
+            Tok::KeywordPrivate => write!(f, "private"),
             Tok::KeywordPrefix => write!(f, "prefix"),
             Tok::KeywordRequire => write!(f, "require"),
             Tok::KeywordTristate => write!(f, "tristate"),
@@ -92,8 +127,13 @@ impl fmt::Display for Tok {
     }
 }
 
-pub fn tokenize(locator: &Locator, s: &str) -> error::Result<Vec<(usize, Tok, usize)>> {
+/// Splits source code into tokens, along with the `netmuncher:allow` comments in it
+pub fn tokenize(
+    locator: &Locator,
+    s: &str,
+) -> error::Result<(Vec<(usize, Tok, usize)>, Vec<Allow>)> {
     let mut tokens = vec![];
+    let mut allows = vec![];
     let mut chars = s.char_indices();
     let mut lookahead = chars.next();
 
@@ -106,19 +146,58 @@ pub fn tokenize(locator: &Locator, s: &str) -> error::Result<Vec<(usize, Tok, us
                 ')' => tokens.push((start, Tok::RParen, start + 1)),
                 '[' => tokens.push((start, Tok::LBracket, start + 1)),
                 ']' => tokens.push((start, Tok::RBracket, start + 1)),
-                '=' => tokens.push((start, Tok::Equals, start + 1)),
-                ',' => tokens.push((start, Tok::Comma, start + 1)),
-                ':' => tokens.push((start, Tok::Colon, start + 1)),
-                ';' => tokens.push((start, Tok::Semicolon, start + 1)),
-                '.' => {
+                '=' => {
+                    let next = chars.next();
+                    if let Some((_, '=')) = next {
+                        tokens.push((start, Tok::EqualsEquals, start + 2));
+                    } else {
+                        tokens.push((start, Tok::Equals, start + 1));
+                        lookahead = next;
+                        continue;
+                    }
+                }
+                '!' => {
                     if let Some((_, c)) = chars.next() {
-                        if c == '.' {
-                            tokens.push((start, Tok::DotDot, start + 2));
+                        if c == '=' {
+                            tokens.push((start, Tok::NotEquals, start + 2));
                         } else {
-                            err!("{}: expected '.', found '{}'", locator.locate(start), c);
+                            err_at!(
+                                locator.sources(),
+                                locator.tag(start),
+                                code::SYNTAX,
+                                "expected '=', found '{}'",
+                                c
+                            );
                         }
                     } else {
-                        err!("{}: unexpected dot", locator.locate(start));
+                        err_at!(
+                            locator.sources(),
+                            locator.tag(start),
+                            code::SYNTAX,
+                            "unexpected '!'"
+                        );
+                    }
+                }
+                ',' => tokens.push((start, Tok::Comma, start + 1)),
+                ':' => {
+                    let next = chars.next();
+                    if let Some((_, ':')) = next {
+                        tokens.push((start, Tok::ColonColon, start + 2));
+                    } else {
+                        tokens.push((start, Tok::Colon, start + 1));
+                        lookahead = next;
+                        continue;
+                    }
+                }
+                ';' => tokens.push((start, Tok::Semicolon, start + 1)),
+                '.' => {
+                    let next = chars.next();
+                    if let Some((_, '.')) = next {
+                        tokens.push((start, Tok::DotDot, start + 2));
+                    } else {
+                        tokens.push((start, Tok::Dot, start + 1));
+                        lookahead = next;
+                        continue;
                     }
                 }
                 '"' => {
@@ -129,23 +208,85 @@ pub fn tokenize(locator: &Locator, s: &str) -> error::Result<Vec<(usize, Tok, us
                 '/' => {
                     if let Some((_, c)) = chars.next() {
                         if c == '/' {
-                            drop(take_while(None, &mut chars, |c| c != '\n'));
+                            let (comment, _) = take_while(None, &mut chars, |c| c != '\n');
+                            if let Some(allow) = parse_allow(locator, start, &comment)? {
+                                allows.push(allow);
+                            }
                         } else {
-                            err!("{}: expected '/', found '{}'", locator.locate(start), c);
+                            err_at!(
+                                locator.sources(),
+                                locator.tag(start),
+                                code::SYNTAX,
+                                "expected '/', found '{}'",
+                                c
+                            );
                         }
                     } else {
-                        err!("{}: unexpected '/'", locator.locate(start));
+                        err_at!(
+                            locator.sources(),
+                            locator.tag(start),
+                            code::SYNTAX,
+                            "unexpected '/'"
+                        );
                     }
                 }
                 _ if c.is_digit(10) => {
                     let (numstr, next) = take_while(Some(c), &mut chars, |c| c.is_digit(10));
-                    lookahead = next;
-                    tokens.push((
-                        start,
-                        Tok::Num(u32::from_str(&numstr).unwrap()),
-                        start + numstr.len(),
-                    ));
-                    continue;
+                    let num = u32::from_str(&numstr).unwrap();
+                    let end = start + numstr.len();
+                    match next {
+                        Some((_, 'V')) => {
+                            tokens.push((start, Tok::Voltage(num * 1000), end + 1));
+                        }
+                        Some((_, '.')) => match chars.next() {
+                            Some((_, '.')) => {
+                                tokens.push((start, Tok::Num(num), end));
+                                tokens.push((end, Tok::DotDot, end + 2));
+                            }
+                            Some((_, c)) if c.is_digit(10) => {
+                                let (fraction, next) =
+                                    take_while(Some(c), &mut chars, |c| c.is_digit(10));
+                                let end = end + 1 + fraction.len();
+                                match next {
+                                    Some((_, 'V')) if fraction.len() <= 3 => {
+                                        let millivolts = u32::from_str(&format!(
+                                            "{:0<3}",
+                                            fraction
+                                        )).unwrap();
+                                        let voltage = num * 1000 + millivolts;
+                                        tokens.push((start, Tok::Voltage(voltage), end + 1));
+                                    }
+                                    Some((_, 'V')) => {
+                                        err_at!(
+                                            locator.sources(),
+                                            locator.tag(start),
+                                            code::SYNTAX,
+                                            "voltages can't be more precise than a millivolt"
+                                        );
+                                    }
+                                    _ => {
+                                        err_at!(
+                                            locator.sources(),
+                                            locator.tag(start),
+                                            code::SYNTAX,
+                                            "expected a voltage such as 3.3V"
+                                        );
+                                    }
+                                }
+                            }
+                            next => {
+                                tokens.push((start, Tok::Num(num), end));
+                                tokens.push((end, Tok::Dot, end + 1));
+                                lookahead = next;
+                                continue;
+                            }
+                        },
+                        _ => {
+                            tokens.push((start, Tok::Num(num), end));
+                            lookahead = next;
+                            continue;
+                        }
+                    }
                 }
                 _ if c.is_ascii() && c.is_alphabetic() => {
                     let (symbol, next) = take_while(Some(c), &mut chars, |c| {
@@ -156,21 +297,32 @@ pub fn tokenize(locator: &Locator, s: &str) -> error::Result<Vec<(usize, Tok, us
 
                     match &symbol as &str {
                         "abstract" => tokens.push((start, Tok::KeywordAbstract, start + 8)),
+                        "as" => tokens.push((start, Tok::KeywordAs, start + 2)),
                         "bidirectional" => {
                             tokens.push((start, Tok::KeywordBidirectional, start + 13))
                         }
+                        "bundle" => tokens.push((start, Tok::KeywordBundle, start + 6)),
                         "component" => tokens.push((start, Tok::KeywordComponent, start + 9)),
                         "connect" => tokens.push((start, Tok::KeywordConnect, start + 7)),
+                        "else" => tokens.push((start, Tok::KeywordElse, start + 4)),
+                        "erc" => tokens.push((start, Tok::KeywordErc, start + 3)),
+                        "extends" => tokens.push((start, Tok::KeywordExtends, start + 7)),
+                        "field" => tokens.push((start, Tok::KeywordField, start + 5)),
                         "footprint" => tokens.push((start, Tok::KeywordFootprint, start + 9)),
+                        "for" => tokens.push((start, Tok::KeywordFor, start + 3)),
                         "global" => tokens.push((start, Tok::KeywordGlobal, start + 6)),
+                        "if" => tokens.push((start, Tok::KeywordIf, start + 2)),
+                        "in" => tokens.push((start, Tok::KeywordIn, start + 2)),
                         "input" => tokens.push((start, Tok::KeywordInput, start + 5)),
                         "net" => tokens.push((start, Tok::KeywordNet, start + 3)),
                         "noconnect" => tokens.push((start, Tok::KeywordNoConnect, start + 9)),
                         "output" => tokens.push((start, Tok::KeywordOutput, start + 6)),
+                        "package" => tokens.push((start, Tok::KeywordPackage, start + 7)),
                         "passive" => tokens.push((start, Tok::KeywordPassive, start + 7)),
                         "pin" => tokens.push((start, Tok::KeywordPin, start + 3)),
                         "power_in" => tokens.push((start, Tok::KeywordPowerIn, start + 8)),
                         "power_out" => tokens.push((start, Tok::KeywordPowerOut, start + 9)),
+                        "private" => tokens.push((start, Tok::KeywordPrivate, start + 7)),
                         "prefix" => tokens.push((start, Tok::KeywordPrefix, start + 6)),
                         "require" => tokens.push((start, Tok::KeywordRequire, start + 7)),
                         "tristate" => tokens.push((start, Tok::KeywordTristate, start + 8)),
@@ -181,14 +333,56 @@ pub fn tokenize(locator: &Locator, s: &str) -> error::Result<Vec<(usize, Tok, us
                     continue;
                 }
                 _ => {
-                    err!("{}: unexpected character: {}", locator.locate(start), c);
+                    err_at!(
+                        locator.sources(),
+                        locator.tag(start),
+                        code::SYNTAX,
+                        "unexpected character: {}",
+                        c
+                    );
                 }
             }
         }
 
         lookahead = chars.next();
     }
-    Ok(tokens)
+    Ok((tokens, allows))
+}
+
+/// Reads a comment that starts at `start`, which might allow some warnings
+fn parse_allow(locator: &Locator, start: usize, comment: &str) -> error::Result<Option<Allow>> {
+    const PREFIX: &'static str = "netmuncher:allow(";
+
+    let directive = comment.trim();
+    if !directive.starts_with(PREFIX) {
+        return Ok(None);
+    }
+    let tag = locator.span(start, start + 2 + comment.trim_end().len());
+    let listed = &directive[PREFIX.len()..];
+    let rules: Vec<String> = match listed.find(')') {
+        Some(end) => listed[..end]
+            .split(',')
+            .map(|rule| rule.trim().to_string())
+            .filter(|rule| !rule.is_empty())
+            .collect(),
+        None => {
+            err_at!(
+                locator.sources(),
+                tag,
+                code::SYNTAX,
+                "expected ')' to close netmuncher:allow"
+            );
+        }
+    };
+    if rules.is_empty() {
+        err_at!(
+            locator.sources(),
+            tag,
+            code::SYNTAX,
+            "netmuncher:allow needs at least one rule"
+        );
+    }
+    Ok(Some(Allow::new(locator.sources(), tag, rules)))
 }
 
 fn take_while<C, F>(c0: Option<char>, chars: &mut C, f: F) -> (String, Option<(usize, char)>)
@@ -215,7 +409,7 @@ where
 
 pub fn validate_symbol(
     locator: &Locator,
-    offset: usize,
+    tag: SrcTag,
     val: String,
 ) -> Result<String, ParseError<usize, Tok, error::Error>> {
     let valid_char = |c: char| c.is_ascii() && c.is_alphanumeric() || c == '_';
@@ -223,12 +417,16 @@ pub fn validate_symbol(
     for c in val.chars() {
         if !valid_char(c) {
             return Err(ParseError::User {
-                error: ErrorKind::NetmuncherError(format!(
-                    "{}: invalid character '{}' in symbol. Symbols must be alphanumeric with \
-                     underscores.",
-                    locator.locate(offset),
-                    c
-                )).into(),
+                error: Diagnostic::new(
+                    locator.sources(),
+                    tag,
+                    code::SYNTAX,
+                    format!(
+                        "invalid character '{}' in symbol. Symbols must be alphanumeric with \
+                         underscores.",
+                        c
+                    )
+                ).into(),
             });
         }
     }
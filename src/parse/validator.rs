@@ -7,18 +7,31 @@
 // copied, modified, or distributed except according to those terms.
 //
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
 
+use diagnostic::{code, Diagnostic, Severity};
 use error;
-use parse::component::{Component, Instance, Pin, PinType};
-use parse::source::Sources;
+use erc::ErcMatrix;
+use options::ErcLevel;
+use parse::component::{Component, Instance, Pin, PinType, ValueExpr};
+use parse::source::{Sources, SrcTag};
+
+/// A pin on a specific copy of an instance
+type NetPin<'a> = (&'a Instance, usize, &'a Pin);
 
 pub struct Validator<'input> {
     sources: &'input Sources,
     global_nets: &'input Vec<String>,
     components: &'input Vec<Component>,
-    global_net_pins: BTreeMap<&'input String, Vec<(&'input Instance, &'input Pin)>>,
-    local_net_pins: BTreeMap<&'input String, Vec<(&'input Instance, &'input Pin)>>,
+    /// Name of the component to compile, which is the root of the circuit
+    top: &'input str,
+    /// ERC tables with the project's overrides
+    erc: &'input ErcMatrix,
+    global_net_pins: BTreeMap<&'input String, Vec<NetPin<'input>>>,
+    local_net_pins: BTreeMap<&'input String, Vec<NetPin<'input>>>,
+    errors: error::Errors,
+    warnings: Vec<Diagnostic>,
 }
 
 impl<'input> Validator<'input> {
@@ -26,79 +39,172 @@ impl<'input> Validator<'input> {
         sources: &'a Sources,
         global_nets: &'a Vec<String>,
         components: &'a Vec<Component>,
+        top: &'a str,
+        erc: &'a ErcMatrix,
     ) -> Validator<'input> {
         Validator {
             sources: sources,
             global_nets: global_nets,
             components: components,
+            top: top,
+            erc: erc,
             global_net_pins: BTreeMap::new(),
             local_net_pins: BTreeMap::new(),
+            errors: error::Errors::new(),
+            warnings: Vec::new(),
         }
     }
 
-    pub fn validate(mut self) -> error::Result<()> {
-        self.validate_global_nets()?;
-        self.validate_components()?;
-        Ok(())
+    /// Runs every check, and fails with all of the errors found if any of them fail, or
+    /// returns the warnings found otherwise
+    pub fn validate(mut self) -> error::Result<Vec<Diagnostic>> {
+        self.validate_components();
+        self.errors.into_result()?;
+        Ok(self.warnings)
     }
 
-    fn validate_global_nets(&mut self) -> error::Result<()> {
-        let unique_nets: HashSet<&String> = self.global_nets.iter().collect();
-        if unique_nets.len() != self.global_nets.len() {
-            err!("detected duplicate global nets");
+    fn report(&mut self, tag: SrcTag, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => self.errors.push_diagnostic(tag, diagnostic),
+            Severity::Warning => {
+                // every expansion of a component finds the warnings of what they share
+                let found = self.warnings
+                    .iter()
+                    .any(|w| w.message == diagnostic.message && w.span == diagnostic.span);
+                if !found {
+                    self.warnings.push(diagnostic);
+                }
+            }
         }
-        Ok(())
     }
 
-    fn validate_components(&mut self) -> error::Result<()> {
-        let mut components: HashSet<String> = HashSet::new();
-        let mut main_component = None;
+    fn validate_components(&mut self) {
+        let mut components: HashMap<&str, SrcTag> = HashMap::new();
+        let mut top_component = None;
         for component in self.components {
-            if components.contains(component.name()) {
-                err!("component {} is defined more than once", component.name());
+            if let Some(&first_tag) = components.get(component.name()) {
+                let message = format!("component {} is defined more than once", component.name());
+                let diagnostic =
+                    Diagnostic::new(self.sources, component.tag, code::DUPLICATE, message)
+                        .with_label(self.sources, first_tag, "first defined here");
+                self.errors.push_diagnostic(component.tag, diagnostic);
+                continue;
             }
-            if component.name() == "Main" {
-                main_component = Some(component);
+            if component.name() == self.top {
+                top_component = Some(component);
             }
-            components.insert(component.name().into());
+            components.insert(component.name(), component.tag);
 
-            component.validate_parameters(self.sources)?;
-            component.validate_units(self.sources)?;
-            self.validate_component(component)?;
+            component.validate_parameters(self.sources, &mut self.errors);
+            component.validate_units(self.sources, &mut self.errors);
+            self.validate_branches(component);
+            for expansion in component.expanded() {
+                self.validate_component(expansion);
+            }
+            self.add_global_net_pins(component);
         }
-        self.validate_nets(&self.global_net_pins)?;
-        self.validate_main(main_component)?;
-        Ok(())
+        let global_net_pins = mem::replace(&mut self.global_net_pins, BTreeMap::new());
+        self.validate_nets(&global_net_pins);
+        self.validate_top(top_component);
     }
 
-    fn validate_main(&mut self, main_component: Option<&Component>) -> error::Result<()> {
-        if let Some(main) = main_component {
-            if !main.is_abstract() {
-                err!(
-                    "{}: component Main must be abstract",
-                    self.sources.locate(main.tag)
-                );
-            }
-            if !main.abstract_pins().is_empty() {
-                err!(
-                    "{}: component Main cannot have pins",
-                    self.sources.locate(main.tag)
-                );
+    fn validate_top(&mut self, top_component: Option<&Component>) {
+        let top = match top_component {
+            Some(top) => top,
+            None => {
+                // only worth reporting on its own, since a component that failed to load
+                // could be the top, and files testing a single component leave it out
+                if self.errors.is_empty() {
+                    let message = format!("missing component {}", self.top);
+                    self.errors.push(None, Diagnostic::unlocated(code::TOP, message).into());
+                }
+                return;
             }
-        } else {
-            err!("missing component Main");
+        };
+        if !top.is_abstract() {
+            report!(
+                self.errors,
+                self.sources,
+                top.tag,
+                code::TOP,
+                "component {} must be abstract",
+                self.top
+            );
+        }
+        if !top.abstract_pins().is_empty() {
+            report!(
+                self.errors,
+                self.sources,
+                top.tag,
+                code::TOP,
+                "component {} cannot have pins",
+                self.top
+            );
+        }
+        if let Some(parameter) = top.parameters.iter().find(|p| p.default.is_none()) {
+            report!(
+                self.errors,
+                self.sources,
+                parameter.tag,
+                code::TOP,
+                "component {} cannot have parameters without defaults",
+                self.top
+            );
         }
-        Ok(())
     }
 
-    fn validate_component(&mut self, component: &'input Component) -> error::Result<()> {
+    fn validate_component(&mut self, component: &'input Component) {
+        if let ValueExpr::Param(ref name) = component.default_value() {
+            if component.find_parameter(name).is_none() {
+                report!(
+                    self.errors,
+                    self.sources,
+                    component.tag,
+                    code::PARAMETER,
+                    "cannot find parameter named {} for value of component {}",
+                    name,
+                    component.name()
+                );
+            }
+        }
+        for &(ref field, ref value) in component.fields() {
+            if let ValueExpr::Param(ref name) = *value {
+                if component.find_parameter(name).is_none() {
+                    report!(
+                        self.errors,
+                        self.sources,
+                        component.tag,
+                        code::PARAMETER,
+                        "cannot find parameter named {} for field {} of component {}",
+                        name,
+                        field,
+                        component.name()
+                    );
+                }
+            }
+        }
         if component.is_abstract() {
-            let mut net_pins: BTreeMap<
-                &'input String,
-                Vec<(&'input Instance, &'input Pin)>,
-            > = BTreeMap::new();
+            let mut labels: HashMap<&String, SrcTag> = HashMap::new();
+            for instance in &component.instances {
+                if let Some(ref label) = instance.label {
+                    if let Some(&first_tag) = labels.get(label) {
+                        let message = format!(
+                            "instance name {} is used more than once in component {}",
+                            label,
+                            component.name()
+                        );
+                        let diagnostic =
+                            Diagnostic::new(self.sources, instance.tag, code::DUPLICATE, message)
+                            .with_label(self.sources, first_tag, "first used here");
+                        self.errors.push_diagnostic(instance.tag, diagnostic);
+                    } else {
+                        labels.insert(label, instance.tag);
+                    }
+                }
+            }
+            let mut net_pins: BTreeMap<&'input String, Vec<NetPin<'input>>> = BTreeMap::new();
             for instance in &component.instances {
-                self.validate_instance(component, instance)?;
+                self.validate_instance(component, instance);
                 for (net_name, pins) in &self.local_net_pins {
                     if !net_pins.contains_key(net_name) {
                         net_pins.insert(net_name, Vec::new());
@@ -109,208 +215,462 @@ impl<'input> Validator<'input> {
             }
             for &(ref left, ref right) in &component.connects {
                 if component.abstract_pins().find_by_name(left).is_none() {
-                    err!(
-                        "{}: could not find pin named '{}' to connect to '{}'",
-                        self.sources.locate(component.tag),
+                    report!(
+                        self.errors,
+                        self.sources,
+                        component.tag,
+                        code::CONNECTION,
+                        "could not find pin named '{}' to connect to '{}'",
                         left,
                         right
                     );
                 }
                 if component.abstract_pins().find_by_name(right).is_none() {
-                    err!(
-                        "{}: could not find pin named '{}' to connect to '{}'",
-                        self.sources.locate(component.tag),
+                    report!(
+                        self.errors,
+                        self.sources,
+                        component.tag,
+                        code::CONNECTION,
+                        "could not find pin named '{}' to connect to '{}'",
                         right,
                         left
                     );
                 }
             }
-            self.validate_nets(&net_pins)?;
+            self.validate_nets(&net_pins);
         }
-        Ok(())
     }
 
-    fn validate_nets(
-        &self,
-        net_pins: &BTreeMap<&'input String, Vec<(&Instance, &Pin)>>,
-    ) -> error::Result<()> {
+    fn validate_nets(&mut self, net_pins: &BTreeMap<&'input String, Vec<NetPin>>) {
         for (net_name, pins) in net_pins {
-            for &(first_instance, first_pin) in pins {
-                for &(second_instance, second_pin) in pins {
-                    if first_instance.tag == second_instance.tag && first_pin == second_pin {
+            // the rules are symmetric, so each pair of pins only needs checking once
+            for (index, &first) in pins.iter().enumerate() {
+                for &second in &pins[(index + 1)..] {
+                    let (first_instance, first_copy, first_pin) = first;
+                    let (second_instance, second_copy, second_pin) = second;
+                    if ::std::ptr::eq(first_instance, second_instance) && first_copy == second_copy
+                        && first_pin == second_pin
+                    {
                         continue;
                     }
-                    self.electronic_rules_check(
-                        net_name,
-                        first_instance,
-                        first_pin,
-                        second_instance,
-                        second_pin,
-                    )?;
+                    if let Some(diagnostic) = self.electronic_rules_check(net_name, first, second) {
+                        self.report(first_instance.tag, diagnostic);
+                    }
                 }
             }
         }
-        Ok(())
     }
 
-    fn add_global_net_pin(
-        &mut self,
-        net: &'input String,
-        instance: &'input Instance,
-        pin: &'input Pin,
-    ) {
+    /// Collects the pins of concrete instances that connect to global nets, counting each
+    /// instance once however many expansions of the component include it
+    fn add_global_net_pins(&mut self, component: &'input Component) {
+        for instance in component.expanded_instances() {
+            let child = match self.find_component(&instance.name) {
+                Some(child) if !child.is_abstract() => child,
+                _ => continue,
+            };
+            for copy in 0..instance.copies() {
+                for pin in &child.first_unit().pins {
+                    if self.global_nets.contains(&pin.name) {
+                        self.add_global_net_pin(&pin.name, (instance, copy, pin));
+                    } else if let Some(mapping) = instance.find_copy_connection(copy, &pin.name) {
+                        if pin.typ != PinType::NoConnect && self.global_nets.contains(mapping) {
+                            self.add_global_net_pin(mapping, (instance, copy, pin));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_global_net_pin(&mut self, net: &'input String, pin: NetPin<'input>) {
         if !self.global_net_pins.contains_key(net) {
             self.global_net_pins.insert(net, Vec::new());
         }
-        self.global_net_pins
-            .get_mut(net)
-            .unwrap()
-            .push((instance, pin));
+        self.global_net_pins.get_mut(net).unwrap().push(pin);
     }
 
-    fn add_local_net_pin(
-        &mut self,
-        net: &'input String,
-        instance: &'input Instance,
-        pin: &'input Pin,
-    ) {
+    fn add_local_net_pin(&mut self, net: &'input String, pin: NetPin<'input>) {
         if !self.local_net_pins.contains_key(net) {
             self.local_net_pins.insert(net, Vec::new());
         }
-        self.local_net_pins
-            .get_mut(net)
-            .unwrap()
-            .push((instance, pin));
+        self.local_net_pins.get_mut(net).unwrap().push(pin);
     }
 
     fn validate_instance(
         &mut self,
         parent_component: &'input Component,
         instance: &'input Instance,
-    ) -> error::Result<()> {
+    ) {
         if let Some(component) = self.find_component(&instance.name) {
-            let unit = component.first_unit();
-            for pin in &unit.pins {
-                if self.global_nets.contains(&pin.name) {
-                    if !component.is_abstract() {
-                        self.add_global_net_pin(&pin.name, instance, pin);
-                    }
+            self.validate_arguments(parent_component, instance, component);
+            for copy in 0..instance.copies() {
+                self.validate_instance_copy(parent_component, instance, copy, component);
+            }
+        } else {
+            report!(
+                self.errors,
+                self.sources,
+                instance.tag,
+                code::INSTANCE,
+                "cannot find component definition for {}",
+                instance.name
+            );
+        }
+    }
+
+    fn validate_instance_copy(
+        &mut self,
+        parent_component: &'input Component,
+        instance: &'input Instance,
+        copy: usize,
+        component: &'input Component,
+    ) {
+        let unit = component.first_unit();
+        for pin in &unit.pins {
+            if self.global_nets.contains(&pin.name) {
+                continue;
+            }
+            if let Some(mapping) = instance.find_copy_connection(copy, &pin.name) {
+                if pin.typ == PinType::NoConnect && mapping != "noconnect" {
+                    report!(
+                        self.errors,
+                        self.sources,
+                        instance.tag,
+                        code::CONNECTION,
+                        "cannot connect noconnect pin named {} in instantiation of \
+                         component {}",
+                        pin.name,
+                        component.name()
+                    );
+                    continue;
+                }
+                if self.global_nets.contains(mapping) {
                     continue;
                 }
-                if let Some(mapping) = instance.find_connection(&pin.name) {
-                    if pin.typ == PinType::NoConnect && mapping != "noconnect" {
-                        err!(
-                            "{}: cannot connect noconnect pin named {} in instantiation of \
+                if mapping != "noconnect" {
+                    if let Some(connected_pin) =
+                        parent_component.abstract_pins().find_by_name(mapping)
+                    {
+                        if let Some(diagnostic) =
+                            self.parameter_rules_check(instance, copy, connected_pin, pin)
+                        {
+                            self.report(instance.tag, diagnostic);
+                        }
+                    } else if parent_component.nets.exists(mapping) {
+                        if !component.is_abstract() {
+                            self.add_local_net_pin(mapping, (instance, copy, pin));
+                        }
+                    } else {
+                        report!(
+                            self.errors,
+                            self.sources,
+                            instance.tag,
+                            code::CONNECTION,
+                            "cannot find pin or net named {} in instantiation of \
                              component {}",
-                            self.sources.locate(instance.tag),
-                            pin.name,
+                            mapping,
                             component.name()
                         );
                     }
-                    if self.global_nets.contains(mapping) {
-                        if !component.is_abstract() {
-                            self.add_global_net_pin(mapping, instance, pin);
+                }
+            } else if pin.typ != PinType::NoConnect {
+                report!(
+                    self.errors,
+                    self.sources,
+                    instance.tag,
+                    code::CONNECTION,
+                    "no connection stated for pin {} on component {}",
+                    pin.name,
+                    component.name()
+                );
+            }
+        }
+    }
+
+    fn validate_branches(&mut self, component: &Component) {
+        for branch in &component.branches {
+            // the enclosing branches check their own conditions
+            let condition = branch.conditions.last().unwrap();
+            match component.find_parameter(&condition.param) {
+                Some(parameter) => {
+                    if parameter.typ != condition.value.typ() {
+                        report!(
+                            self.errors,
+                            self.sources,
+                            branch.tag,
+                            code::PARAMETER,
+                            "parameter {} of component {} is a {}, but is compared to a {}",
+                            parameter.name,
+                            component.name(),
+                            parameter.typ,
+                            condition.value.typ()
+                        );
+                    }
+                }
+                None => {
+                    report!(
+                        self.errors,
+                        self.sources,
+                        branch.tag,
+                        code::PARAMETER,
+                        "cannot find parameter named {} for if block in component {}",
+                        condition.param,
+                        component.name()
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_arguments(
+        &mut self,
+        parent_component: &Component,
+        instance: &Instance,
+        component: &Component,
+    ) {
+        // short names to avoid line wrapping on errors
+        let n = component.name();
+        let tag = instance.tag;
+
+        if let Some(&ValueExpr::Param(ref name)) = instance.value() {
+            if parent_component.find_parameter(name).is_none() {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::PARAMETER,
+                    "cannot find parameter named {} for value of component {}",
+                    name,
+                    n
+                );
+            }
+        }
+
+        for &(ref field, ref value) in &instance.fields {
+            if component.is_abstract() {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::INSTANCE,
+                    "cannot set field {} on instance of abstract component {}",
+                    field,
+                    n
+                );
+            }
+            if let ValueExpr::Param(ref name) = *value {
+                if parent_component.find_parameter(name).is_none() {
+                    report!(
+                        self.errors,
+                        self.sources,
+                        tag,
+                        code::PARAMETER,
+                        "cannot find parameter named {} for field {} of component {}",
+                        name,
+                        field,
+                        n
+                    );
+                }
+            }
+        }
+
+        if let Some(ref package) = instance.package {
+            if component.is_abstract() {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::INSTANCE,
+                    "cannot choose a package for abstract component {}",
+                    n
+                );
+            }
+            if component.find_package(package).is_none() {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::INSTANCE,
+                    "component {} has no package named {}",
+                    n,
+                    package
+                );
+            }
+        }
+
+        let mut bound: Vec<&str> = Vec::new();
+        // arguments that don't match a parameter are probably meant for a missing one
+        let mut unmatched = false;
+        let mut seen_named = false;
+        for (index, argument) in instance.arguments.iter().enumerate() {
+            let parameter = match argument.name {
+                Some(ref name) => {
+                    seen_named = true;
+                    match component.find_parameter(name) {
+                        Some(parameter) => parameter,
+                        None => {
+                            report!(
+                                self.errors,
+                                self.sources,
+                                tag,
+                                code::PARAMETER,
+                                "component {} has no parameter named {}",
+                                n,
+                                name
+                            );
+                            unmatched = true;
+                            continue;
                         }
-                        continue;
                     }
-                    if mapping != "noconnect" {
-                        if let Some(connected_pin) =
-                            parent_component.abstract_pins().find_by_name(mapping)
-                        {
-                            self.parameter_rules_check(instance, connected_pin, pin)?;
-                        } else if parent_component.nets.exists(mapping) {
-                            if !component.is_abstract() {
-                                self.add_local_net_pin(mapping, instance, pin);
-                            }
-                        } else {
-                            err!(
-                                "{}: cannot find pin or net named {} in instantiation of \
-                                 component {}",
-                                self.sources.locate(instance.tag),
-                                mapping,
-                                component.name()
+                }
+                None => {
+                    if seen_named {
+                        report!(
+                            self.errors,
+                            self.sources,
+                            tag,
+                            code::PARAMETER,
+                            "positional arguments to component {} must come before named \
+                             arguments",
+                            n
+                        );
+                    }
+                    match component.parameters.get(index) {
+                        Some(parameter) => parameter,
+                        None => {
+                            report!(
+                                self.errors,
+                                self.sources,
+                                tag,
+                                code::PARAMETER,
+                                "component {} takes {} parameters, but {} arguments were \
+                                 given",
+                                n,
+                                component.parameters.len(),
+                                instance.arguments.len()
                             );
+                            unmatched = true;
+                            continue;
                         }
                     }
-                } else if pin.typ != PinType::NoConnect {
-                    err!(
-                        "{}: no connection stated for pin {} on component {}",
-                        self.sources.locate(instance.tag),
-                        pin.name,
-                        component.name()
-                    );
                 }
+            };
+            if bound.contains(&&parameter.name[..]) {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::PARAMETER,
+                    "parameter {} of component {} is given more than once",
+                    parameter.name,
+                    n
+                );
+            }
+            bound.push(&parameter.name);
+
+            let typ = match argument.value {
+                ValueExpr::Literal(ref value) => value.typ(),
+                ValueExpr::Param(ref name) => match parent_component.find_parameter(name) {
+                    Some(parent_parameter) => parent_parameter.typ,
+                    None => {
+                        report!(
+                            self.errors,
+                            self.sources,
+                            tag,
+                            code::PARAMETER,
+                            "cannot find parameter named {} in instantiation of component {}",
+                            name,
+                            n
+                        );
+                        continue;
+                    }
+                },
+            };
+            if typ != parameter.typ {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::PARAMETER,
+                    "parameter {} of component {} must be a {}, but was given a {}",
+                    parameter.name,
+                    n,
+                    parameter.typ,
+                    typ
+                );
+            }
+        }
+
+        for parameter in &component.parameters {
+            if parameter.default.is_none() && !bound.contains(&&parameter.name[..]) && !unmatched {
+                report!(
+                    self.errors,
+                    self.sources,
+                    tag,
+                    code::PARAMETER,
+                    "missing argument for parameter {} of component {}",
+                    parameter.name,
+                    n
+                );
             }
-        } else {
-            err!(
-                "{}: cannot find component definition for {}",
-                self.sources.locate(instance.tag),
-                instance.name
-            );
         }
-        Ok(())
     }
 
     fn parameter_rules_check(
         &self,
         instance: &Instance,
+        copy: usize,
         instance_pin: &Pin,
         other_pin: &Pin,
-    ) -> error::Result<()> {
-        match check_parameter_connection(instance_pin.typ, other_pin.typ) {
-            ERCResult::Valid => Ok(()),
-            r @ ERCResult::Warning | r @ ERCResult::Error => {
-                let error = error::ErrorKind::NetmuncherError(format!(
-                    "{}: in instantiation of {}, pin {} ({:?}) mapped to {} ({:?})",
-                    self.sources.locate(instance.tag),
-                    instance.name,
-                    instance_pin.name,
-                    instance_pin.typ,
-                    other_pin.name,
-                    other_pin.typ
-                ));
-                if r == ERCResult::Warning {
-                    println!("WARN: {}", error);
-                    Ok(())
-                } else {
-                    Err(error.into())
-                }
-            }
-        }
+    ) -> Option<Diagnostic> {
+        let severity = match self.erc.check_mapping(instance_pin.typ, other_pin.typ) {
+            ERCResult::Valid => return None,
+            ERCResult::Warning => Severity::Warning,
+            ERCResult::Error => Severity::Error,
+        };
+        let code = format!("erc-mapping-{}", pin_types_code(instance_pin.typ, other_pin.typ));
+        let message = format!(
+            "in instantiation of {}, pin {} ({:?}) mapped to {} ({:?})",
+            instance.copy_name(copy),
+            instance_pin.name,
+            instance_pin.typ,
+            other_pin.name,
+            other_pin.typ
+        );
+        Some(Diagnostic::new(self.sources, instance.tag, &code, message).with_severity(severity))
     }
 
     fn electronic_rules_check(
         &self,
         net: &str,
-        first_instance: &Instance,
-        first_pin: &Pin,
-        second_instance: &Instance,
-        second_pin: &Pin,
-    ) -> error::Result<()> {
-        match check_electric_connection(first_pin.typ, second_pin.typ) {
-            ERCResult::Valid => Ok(()),
-            r @ ERCResult::Warning | r @ ERCResult::Error => {
-                let error = error::ErrorKind::NetmuncherError(format!(
-                    "{}: in instantiation of {}, pin {} ({:?}) is connected by net {} to pin {} \
-                     ({:?}) of instantiation {} at {}",
-                    self.sources.locate(first_instance.tag),
-                    first_instance.name,
-                    first_pin.name,
-                    first_pin.typ,
-                    net,
-                    second_pin.name,
-                    second_pin.typ,
-                    second_instance.name,
-                    self.sources.locate(second_instance.tag),
-                ));
-                if r == ERCResult::Warning {
-                    println!("WARN: {}", error);
-                    Ok(())
-                } else {
-                    Err(error.into())
-                }
-            }
-        }
+        first: NetPin,
+        second: NetPin,
+    ) -> Option<Diagnostic> {
+        let (first_instance, first_copy, first_pin) = first;
+        let (second_instance, second_copy, second_pin) = second;
+        let severity = match self.erc.check_connection(first_pin.typ, second_pin.typ) {
+            ERCResult::Valid => return None,
+            ERCResult::Warning => Severity::Warning,
+            ERCResult::Error => Severity::Error,
+        };
+        let message = format!(
+            "in instantiation of {}, pin {} ({:?}) is connected by net {} to pin {} ({:?}) of \
+             instantiation {} at {}",
+            first_instance.copy_name(first_copy),
+            first_pin.name,
+            first_pin.typ,
+            net,
+            second_pin.name,
+            second_pin.typ,
+            second_instance.copy_name(second_copy),
+            self.sources.locate(second_instance.tag)
+        );
+        let code = erc_code(first_pin.typ, second_pin.typ);
+        let diagnostic = Diagnostic::new(self.sources, first_instance.tag, &code, message)
+            .with_label(self.sources, second_instance.tag, "connected to this instance")
+            .with_severity(severity);
+        Some(diagnostic)
     }
 
     fn find_component(&self, name: &str) -> Option<&'input Component> {
@@ -319,12 +679,68 @@ impl<'input> Validator<'input> {
 }
 
 #[derive(Debug, Eq, PartialEq)]
-enum ERCResult {
+pub enum ERCResult {
     Valid,
     Warning,
     Error,
 }
 
+/// Code of the ERC diagnostic for a connection between two pin types, which names them in
+/// the order they're declared in `PinType` so that it's the same either way around
+pub fn erc_code(first: PinType, second: PinType) -> String {
+    if (first as usize) <= (second as usize) {
+        format!("erc-{}", pin_types_code(first, second))
+    } else {
+        format!("erc-{}", pin_types_code(second, first))
+    }
+}
+
+fn pin_types_code(first: PinType, second: PinType) -> String {
+    format!(
+        "{}-{}",
+        first.keyword().replace('_', "-"),
+        second.keyword().replace('_', "-")
+    )
+}
+
+/// Checks a parent's pin mapped to a child's pin, with the last matching override winning
+pub fn check_mapping(
+    overrides: &[(PinType, PinType, ErcLevel)],
+    parent: PinType,
+    child: PinType,
+) -> ERCResult {
+    let level = overrides
+        .iter()
+        .rev()
+        .find(|o| (o.0, o.1) == (parent, child))
+        .map(|o| o.2);
+    match level {
+        Some(ErcLevel::Ok) => ERCResult::Valid,
+        Some(ErcLevel::Warning) => ERCResult::Warning,
+        Some(ErcLevel::Error) => ERCResult::Error,
+        None => check_parameter_connection(parent, child),
+    }
+}
+
+/// Checks a connection between two pin types, with the last matching override winning
+pub fn check_connection(
+    overrides: &[(PinType, PinType, ErcLevel)],
+    first: PinType,
+    second: PinType,
+) -> ERCResult {
+    let level = overrides
+        .iter()
+        .rev()
+        .find(|o| (o.0, o.1) == (first, second) || (o.0, o.1) == (second, first))
+        .map(|o| o.2);
+    match level {
+        Some(ErcLevel::Ok) => ERCResult::Valid,
+        Some(ErcLevel::Warning) => ERCResult::Warning,
+        Some(ErcLevel::Error) => ERCResult::Error,
+        None => check_electric_connection(first, second),
+    }
+}
+
 fn check_parameter_connection(parent_pin: PinType, child_pin: PinType) -> ERCResult {
     use self::ERCResult::*;
     use parse::component::PinType::*;
@@ -0,0 +1,529 @@
+//
+// Copyright 2018 netmuncher Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! A system is a set of boards that are wired together by cables or mating connectors.
+//! Each board is compiled from its own top component to its own netlist, and the links
+//! between them are listed in the project manifest:
+//!
+//! ```toml
+//! entry = "system.nm"
+//! wiring-report = "build/wiring.txt"
+//!
+//! [[board]]
+//! name = "backplane"
+//! top = "Backplane"
+//! output = "build/backplane.net"
+//!
+//! [[board]]
+//! name = "card"
+//! top = "Card"
+//! output = "build/card.net"
+//!
+//! [[link]]
+//! from = "backplane.J1"
+//! to = "card.P1"
+//! ```
+//!
+//! Linked connectors are joined pin for pin, unless the link lists the pins of `to` that
+//! each pin of `from` is wired to with `pins = { "1" = "4", "2" = "3" }`, which has to
+//! include every pin that's connected on either board. The nets joined across boards are
+//! checked end to end with the same electrical rules as a single board.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use circuit::{Circuit, Net};
+use erc::ErcMatrix;
+use diagnostic::{self, code, Diagnostic, Severity};
+use error::{self, ErrorKind, ResultExt};
+use manifest::{Link, Manifest};
+use options::Options;
+use parse::component::PinNum;
+use parse::source::{Sources, SrcTag};
+use parse::validator::{erc_code, ERCResult};
+
+#[derive(Debug)]
+pub struct Board {
+    pub name: String,
+    pub circuit: Circuit,
+}
+
+/// A pin at one end of a link, and the net it's connected to on its board
+#[derive(Debug)]
+pub struct LinkEnd {
+    pub pin: PinNum,
+    pub net: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct LinkWiring {
+    pub from: String,
+    pub to: String,
+    /// Each pair of pins joined by the link
+    pub pins: Vec<(LinkEnd, LinkEnd)>,
+}
+
+#[derive(Debug)]
+pub struct System {
+    pub boards: Vec<Board>,
+    pub links: Vec<LinkWiring>,
+    /// Nets joined across boards, as the name of each board and its net
+    pub nets: Vec<Vec<(String, String)>>,
+    /// Problems with connections across boards that didn't stop the system from compiling,
+    /// apart from those in each board's circuit
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl System {
+    pub fn compile(manifest: &Manifest, options: &Options) -> error::Result<System> {
+        let sources = manifest.sources();
+        if manifest.boards.is_empty() {
+            let message = "the manifest doesn't list any boards".into();
+            return Err(Diagnostic::new(&sources, SrcTag::new(0, 0), code::SYSTEM, message).into());
+        }
+
+        let mut boards = Vec::new();
+        for (index, board) in manifest.boards.iter().enumerate() {
+            let mut board_options = options.clone();
+            board_options.top = Some(board.top.clone());
+            board_options.manifest = Some(manifest.clone());
+            let entry = manifest.board_entry_path(board)?;
+            let circuit = Circuit::compile_with_options(&entry.to_string_lossy(), &board_options)
+                .chain_err(|| {
+                    let tag = manifest.entry_tag("board", index);
+                    let message = format!("error in board {}", board.name);
+                    ErrorKind::Diagnostic(Diagnostic::new(&sources, tag, code::SYSTEM, message))
+                })?;
+            boards.push(Board {
+                name: board.name.clone(),
+                circuit: circuit,
+            });
+        }
+
+        let mut system = System {
+            boards: boards,
+            links: Vec::new(),
+            nets: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let mut errors = error::Errors::new();
+        let mut joiner = NetJoiner::new();
+        for (index, link) in manifest.links.iter().enumerate() {
+            let tag = manifest.entry_tag("link", index);
+            if let Some(wiring) = system.wire(&sources, tag, link, &mut joiner, &mut errors) {
+                system.links.push(wiring);
+            }
+        }
+        errors.into_result()?;
+        system.nets = joiner.joined_nets();
+
+        let erc = ErcMatrix::for_project(Some(manifest), options)?;
+        let warnings = system.check(&sources, &joiner, &erc)?;
+        system.warnings = diagnostic::review_warnings(warnings, &[], options.deny_warnings)?;
+        Ok(system)
+    }
+
+    fn find_board(&self, name: &str) -> Option<&Board> {
+        self.boards.iter().find(|b| b.name == name)
+    }
+
+    /// Finds the connector at one end of a link, and the net each of its pins is connected to
+    fn connector(
+        &self,
+        sources: &Sources,
+        tag: SrcTag,
+        end: &str,
+    ) -> error::Result<(&Board, BTreeMap<String, String>)> {
+        let (board_name, reference) = match end.find('.') {
+            Some(index) => (&end[0..index], &end[(index + 1)..]),
+            None => {
+                err_at!(
+                    sources,
+                    tag,
+                    code::SYSTEM,
+                    "link end {} should name a board and connector like backplane.J1",
+                    end
+                );
+            }
+        };
+        let board = match self.find_board(board_name) {
+            Some(board) => board,
+            None => {
+                err_at!(
+                    sources,
+                    tag,
+                    code::SYSTEM,
+                    "cannot find board {} for link end {}",
+                    board_name,
+                    end
+                );
+            }
+        };
+        let circuit = &board.circuit;
+        if !circuit.instances.iter().any(|i| i.reference() == reference) {
+            err_at!(
+                sources,
+                tag,
+                code::SYSTEM,
+                "cannot find connector {} on board {}",
+                reference,
+                board_name
+            );
+        }
+        let mut nets = BTreeMap::new();
+        for net in &circuit.nets {
+            for node in net.nodes.iter().filter(|n| n.reference == reference) {
+                nets.insert(node.pin.0.clone(), net.name.clone());
+            }
+        }
+        Ok((board, nets))
+    }
+
+    /// Joins the nets connected by a link, reporting the pins that it leaves unconnected and
+    /// the nets of a board that it shorts together
+    fn wire(
+        &self,
+        sources: &Sources,
+        tag: SrcTag,
+        link: &Link,
+        joiner: &mut NetJoiner,
+        errors: &mut error::Errors,
+    ) -> Option<LinkWiring> {
+        let from = errors.check(Some(tag), self.connector(sources, tag, &link.from));
+        let to = errors.check(Some(tag), self.connector(sources, tag, &link.to));
+        let ((from_board, from_nets), (to_board, to_nets)) = match (from, to) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return None,
+        };
+
+        let mut pairs: Vec<(PinNum, PinNum)> = if link.pins.is_empty() {
+            from_nets
+                .keys()
+                .chain(to_nets.keys())
+                .map(|pin| (PinNum(pin.clone()), PinNum(pin.clone())))
+                .collect()
+        } else {
+            link.pins
+                .iter()
+                .map(|(from, to)| (PinNum(from.clone()), PinNum(to.clone())))
+                .collect()
+        };
+        pairs.sort_by_key(|&(ref from, _)| pin_order(from));
+        pairs.dedup();
+
+        if !link.pins.is_empty() {
+            let from_pins: Vec<&String> = link.pins.keys().collect();
+            let to_pins: Vec<&String> = link.pins.values().collect();
+            let ends = [(&link.from, &from_nets, from_pins), (&link.to, &to_nets, to_pins)];
+            for &(end, nets, ref pins) in &ends {
+                for (pin, net) in nets.iter().filter(|&(pin, _)| !pins.contains(&pin)) {
+                    report!(
+                        errors,
+                        sources,
+                        tag,
+                        code::SYSTEM,
+                        "pin {} of {} is connected to net {}, but the link's pins leave it out",
+                        pin,
+                        end,
+                        net
+                    );
+                }
+            }
+        }
+
+        let mut pins = Vec::new();
+        for (from_pin, to_pin) in pairs {
+            let from_net = from_nets.get(&from_pin.0).cloned();
+            let to_net = to_nets.get(&to_pin.0).cloned();
+            match (&from_net, &to_net) {
+                (&Some(ref from_net), &Some(ref to_net)) => {
+                    let from_end = (from_board.name.as_str(), from_net.as_str());
+                    let to_end = (to_board.name.as_str(), to_net.as_str());
+                    if let Some((board, first, second)) = joiner.join(from_end, to_end, tag) {
+                        report!(
+                            errors,
+                            sources,
+                            tag,
+                            code::SYSTEM,
+                            "links join nets {} and {} of board {}",
+                            first,
+                            second,
+                            board
+                        );
+                    }
+                }
+                (&Some(ref net), &None) => {
+                    report!(
+                        errors,
+                        sources,
+                        tag,
+                        code::SYSTEM,
+                        "pin {} of {} is connected to net {}, but pin {} of {} isn't connected",
+                        from_pin,
+                        link.from,
+                        net,
+                        to_pin,
+                        link.to
+                    );
+                }
+                (&None, &Some(ref net)) => {
+                    report!(
+                        errors,
+                        sources,
+                        tag,
+                        code::SYSTEM,
+                        "pin {} of {} is connected to net {}, but pin {} of {} isn't connected",
+                        to_pin,
+                        link.to,
+                        net,
+                        from_pin,
+                        link.from
+                    );
+                }
+                (&None, &None) => {}
+            }
+            pins.push((
+                LinkEnd {
+                    pin: from_pin,
+                    net: from_net,
+                },
+                LinkEnd {
+                    pin: to_pin,
+                    net: to_net,
+                },
+            ));
+        }
+
+        Some(LinkWiring {
+            from: link.from.clone(),
+            to: link.to.clone(),
+            pins: pins,
+        })
+    }
+
+    /// Checks that the pins of nets joined across boards can be connected to each other,
+    /// failing with all of the errors found, or returning the warnings otherwise
+    fn check(
+        &self,
+        sources: &Sources,
+        joiner: &NetJoiner,
+        erc: &ErcMatrix,
+    ) -> error::Result<Vec<Diagnostic>> {
+        let mut errors = error::Errors::new();
+        let mut warnings = Vec::new();
+        for joined in &self.nets {
+            for (index, first) in joined.iter().enumerate() {
+                for second in &joined[(index + 1)..] {
+                    // the link that brought the second net into the set joins the two
+                    let tag = joiner.link_tag(&second.0, &second.1);
+                    for diagnostic in self.electronic_rules_check(sources, tag, erc, first, second)
+                    {
+                        match diagnostic.severity {
+                            Severity::Error => errors.push_diagnostic(tag, diagnostic),
+                            Severity::Warning => warnings.push(diagnostic),
+                        }
+                    }
+                }
+            }
+        }
+        errors.into_result()?;
+        Ok(warnings)
+    }
+
+    fn electronic_rules_check(
+        &self,
+        sources: &Sources,
+        tag: SrcTag,
+        erc: &ErcMatrix,
+        first: &(String, String),
+        second: &(String, String),
+    ) -> Vec<Diagnostic> {
+        let mut findings = Vec::new();
+        let first_nodes = self.find_net(first).distinct_pins();
+        let second_nodes = self.find_net(second).distinct_pins();
+        for first_node in &first_nodes {
+            for second_node in &second_nodes {
+                let (first_type, second_type) = (first_node.pin_type, second_node.pin_type);
+                let severity = match erc.check_connection(first_type, second_type) {
+                    ERCResult::Valid => continue,
+                    ERCResult::Warning => Severity::Warning,
+                    ERCResult::Error => Severity::Error,
+                };
+                let message = format!(
+                    "pin {} ({:?}) of {}.{} on net {} is connected across boards to pin {} \
+                     ({:?}) of {}.{} on net {}",
+                    first_node.pin_name,
+                    first_node.pin_type,
+                    first.0,
+                    first_node.reference,
+                    first.1,
+                    second_node.pin_name,
+                    second_node.pin_type,
+                    second.0,
+                    second_node.reference,
+                    second.1
+                );
+                let code = erc_code(first_type, second_type);
+                let diagnostic = Diagnostic::new(sources, tag, &code, message);
+                findings.push(diagnostic.with_severity(severity));
+            }
+        }
+        findings
+    }
+
+    fn find_net(&self, &(ref board, ref net): &(String, String)) -> &Net {
+        let board = self.find_board(board).unwrap();
+        board.circuit.nets.iter().find(|n| n.name == *net).unwrap()
+    }
+
+    /// Lists the pins each link joins and the nets they join, for checking the connector
+    /// pinouts of a system by hand
+    pub fn wiring_report(&self) -> String {
+        let mut report = String::new();
+        for link in &self.links {
+            let rows: Vec<[String; 4]> = link.pins
+                .iter()
+                .map(|&(ref from, ref to)| {
+                    [
+                        from.pin.to_string(),
+                        from.net.clone().unwrap_or_else(|| "-".into()),
+                        to.pin.to_string(),
+                        to.net.clone().unwrap_or_else(|| "-".into()),
+                    ]
+                })
+                .collect();
+            let mut widths = [3, 3, 3];
+            for row in &rows {
+                for (width, column) in widths.iter_mut().zip(row.iter()) {
+                    *width = (*width).max(column.len());
+                }
+            }
+
+            writeln!(report, "{} to {}", link.from, link.to).unwrap();
+            let header = ["pin".into(), "net".into(), "pin".into(), "net".into()];
+            for row in Some(&header).into_iter().chain(rows.iter()) {
+                let mut line = String::from(" ");
+                for (width, column) in widths.iter().zip(row.iter()) {
+                    write!(line, " {:<width$}", column, width = width).unwrap();
+                }
+                write!(line, " {}", row[3]).unwrap();
+                if row[1] != row[3] && row[1] != "-" && row[3] != "-" {
+                    line.push_str(" (renamed)");
+                }
+                writeln!(report, "{}", line).unwrap();
+            }
+            writeln!(report).unwrap();
+        }
+
+        writeln!(report, "Nets joined across boards").unwrap();
+        for joined in &self.nets {
+            let names: Vec<String> = joined
+                .iter()
+                .map(|&(ref board, ref net)| format!("{}.{}", board, net))
+                .collect();
+            writeln!(report, "  {}", names.join(", ")).unwrap();
+        }
+        report
+    }
+}
+
+/// Sorts numbered pins by number, ahead of alphanumeric ones
+fn pin_order(pin: &PinNum) -> (u32, String) {
+    (pin.as_number().unwrap_or(u32::MAX), pin.0.clone())
+}
+
+/// Tracks which board nets links join together, as a disjoint set forest
+struct NetJoiner {
+    nets: Vec<(String, String)>,
+    parents: Vec<usize>,
+    /// The link that first joined each net
+    links: Vec<SrcTag>,
+}
+
+impl NetJoiner {
+    fn new() -> NetJoiner {
+        NetJoiner {
+            nets: Vec::new(),
+            parents: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    fn index(&mut self, board: &str, net: &str, link: SrcTag) -> usize {
+        match self.nets.iter().position(|n| n.0 == board && n.1 == net) {
+            Some(index) => index,
+            None => {
+                self.nets.push((board.into(), net.into()));
+                self.parents.push(self.parents.len());
+                self.links.push(link);
+                self.parents.len() - 1
+            }
+        }
+    }
+
+    fn root(&self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            index = self.parents[index];
+        }
+        index
+    }
+
+    /// Joins two nets, returning a board and two of its nets if that shorts them together
+    fn join(
+        &mut self,
+        first: (&str, &str),
+        second: (&str, &str),
+        link: SrcTag,
+    ) -> Option<(String, String, String)> {
+        let first = self.index(first.0, first.1, link);
+        let second = self.index(second.0, second.1, link);
+        let (first_root, second_root) = (self.root(first), self.root(second));
+        if first_root == second_root {
+            return None;
+        }
+        let members = |root: usize| -> Vec<&(String, String)> {
+            (0..self.nets.len())
+                .filter(|&index| self.root(index) == root)
+                .map(|index| &self.nets[index])
+                .collect()
+        };
+        let mut short = None;
+        for first_net in members(first_root) {
+            for second_net in members(second_root) {
+                if short.is_none() && first_net.0 == second_net.0 {
+                    short = Some((first_net.0.clone(), first_net.1.clone(), second_net.1.clone()));
+                }
+            }
+        }
+        self.parents[second_root] = first_root;
+        short
+    }
+
+    fn link_tag(&self, board: &str, net: &str) -> SrcTag {
+        let index = self.nets.iter().position(|n| n.0 == board && n.1 == net).unwrap();
+        self.links[index]
+    }
+
+    /// Sets of nets that were joined, in the order they were first linked
+    fn joined_nets(&self) -> Vec<Vec<(String, String)>> {
+        let mut roots: Vec<usize> = Vec::new();
+        let mut joined: Vec<Vec<(String, String)>> = Vec::new();
+        for (index, net) in self.nets.iter().enumerate() {
+            let root = self.root(index);
+            match roots.iter().position(|&r| r == root) {
+                Some(position) => joined[position].push(net.clone()),
+                None => {
+                    roots.push(root);
+                    joined.push(vec![net.clone()]);
+                }
+            }
+        }
+        joined
+    }
+}
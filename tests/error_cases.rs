@@ -9,9 +9,14 @@
 
 extern crate error_chain;
 extern crate netmuncher;
+extern crate serde_json;
 
 use error_chain::ChainedError;
 use netmuncher::circuit::Circuit;
+use netmuncher::diagnostic::{self, MessageFormat};
+use netmuncher::manifest::Manifest;
+use netmuncher::options::Options;
+use netmuncher::system::System;
 
 fn test(file_name: &str) -> String {
     Circuit::compile(file_name)
@@ -21,10 +26,43 @@ fn test(file_name: &str) -> String {
         .to_string()
 }
 
+fn test_rendered(file_name: &str) -> String {
+    let error = Circuit::compile(file_name)
+        .err()
+        .expect("expected error, but there was none");
+    diagnostic::render(&error)
+}
+
+fn test_formatted(file_name: &str, format: MessageFormat) -> String {
+    let error = Circuit::compile(file_name)
+        .err()
+        .expect("expected error, but there was none");
+    format.format(&[], Some(&error))
+}
+
+fn test_denied(file_name: &str) -> String {
+    let mut options = Options::new();
+    options.deny_warnings = true;
+    Circuit::compile_with_options(file_name, &options)
+        .err()
+        .expect("expected error, but there was none")
+        .display_chain()
+        .to_string()
+}
+
+fn test_system(manifest_path: &str) -> String {
+    let manifest = Manifest::load(manifest_path).unwrap();
+    System::compile(&manifest, &Options::new())
+        .err()
+        .expect("expected error, but there was none")
+        .display_chain()
+        .to_string()
+}
+
 #[test]
 fn empty_file() {
     assert_eq!(
-        "Error: tests/errors/empty_file.nm: unexpected end of file\n",
+        "Error: tests/errors/empty_file.nm:1:1: unexpected end of file\n",
         test("tests/errors/empty_file.nm")
     );
 }
@@ -33,7 +71,7 @@ fn empty_file() {
 fn unexpected_token() {
     assert_eq!(
         "Error: tests/errors/unexpected_token.nm:2:7: unexpected token \"=\". Expected one of: \
-         \";\", \"{\"\n",
+         \"(\", \"::\", \";\", \"[\", \"{\", Symbol\n",
         test("tests/errors/unexpected_token.nm")
     );
 }
@@ -47,6 +85,17 @@ fn duplicate_pin() {
     );
 }
 
+#[test]
+fn duplicate_pins() {
+    assert_eq!(
+        "Error: tests/errors/duplicate_pins.nm:1:1: error in component Foo\nCaused by: \
+         tests/errors/duplicate_pins.nm:7:5: duplicate pin named A\nError: \
+         tests/errors/duplicate_pins.nm:1:1: error in component Foo\nCaused by: \
+         tests/errors/duplicate_pins.nm:7:5: duplicate pin named B\n",
+        test("tests/errors/duplicate_pins.nm")
+    );
+}
+
 #[test]
 fn wrong_start_pin() {
     assert_eq!(
@@ -140,10 +189,19 @@ fn missing_pins() {
     );
 }
 
+#[test]
+fn missing_pins_exposed_pad() {
+    assert_eq!(
+        "Error: tests/errors/missing_pins_exposed_pad.nm:1:1: component Regulator is missing some \
+         pins (take a look at pin 3)\n",
+        test("tests/errors/missing_pins_exposed_pad.nm")
+    );
+}
+
 #[test]
 fn duplicate_component() {
     assert_eq!(
-        "Error: component Foo is defined more than once\n",
+        "Error: tests/errors/duplicate_component.nm:4:1: component Foo is defined more than once\n",
         test("tests/errors/duplicate_component.nm")
     );
 }
@@ -296,7 +354,10 @@ fn concrete_component_requires_pin_num() {
     assert_eq!(
         "Error: tests/errors/concrete_component_require_pin_num.nm:1:1: error in component \
          Foo\nCaused by: tests/errors/concrete_component_require_pin_num.nm:5:5: concrete \
-         components must state pin numbers for pins\n",
+         components must state pin numbers for pins\nError: \
+         tests/errors/concrete_component_require_pin_num.nm:1:1: error in component Foo\nCaused \
+         by: tests/errors/concrete_component_require_pin_num.nm:6:5: concrete components must \
+         state pin numbers for pins\n",
         test("tests/errors/concrete_component_require_pin_num.nm")
     );
 }
@@ -306,7 +367,9 @@ fn abstract_component_no_pin_num() {
     assert_eq!(
         "Error: tests/errors/abstract_component_no_pin_num.nm:1:1: error in component Foo\nCaused \
          by: tests/errors/abstract_component_no_pin_num.nm:2:5: abstract components shouldn\'t \
-         state pin numbers for pins\n",
+         state pin numbers for pins\nError: tests/errors/abstract_component_no_pin_num.nm:1:1: \
+         error in component Foo\nCaused by: tests/errors/abstract_component_no_pin_num.nm:3:5: \
+         abstract components shouldn\'t state pin numbers for pins\n",
         test("tests/errors/abstract_component_no_pin_num.nm")
     );
 }
@@ -342,7 +405,8 @@ fn duplicate_instance_value() {
 fn connect_nets() {
     assert_eq!(
         "Error: tests/errors/connect_nets.nm:1:1: could not find pin named \'C\' to connect to \
-         \'A\'\n",
+         \'A\'\nError: tests/errors/connect_nets.nm:1:1: could not find pin named \'C\' to connect \
+         to \'B\'\n",
         test("tests/errors/connect_nets.nm")
     );
 }
@@ -351,7 +415,496 @@ fn connect_nets() {
 fn require_doesnt_exist() {
     assert_eq!(
         "Error: tests/errors/require_doesnt_exist.nm:1:1: cannot find file named \
-         \"a_file_that_doesnt_exist.nm\"\n",
+         \"a_file_that_doesnt_exist.nm\" (tried tests/errors/a_file_that_doesnt_exist.nm)\n",
         test("tests/errors/require_doesnt_exist.nm")
     );
 }
+
+#[test]
+fn parameter_unknown_name() {
+    assert_eq!(
+        "Error: tests/errors/parameter_unknown_name.nm:13:5: component R has no parameter named \
+         ohms\n",
+        test("tests/errors/parameter_unknown_name.nm")
+    );
+}
+
+#[test]
+fn parameter_too_many_arguments() {
+    assert_eq!(
+        "Error: tests/errors/parameter_too_many_arguments.nm:13:5: component R takes 1 \
+         parameters, but 2 arguments were given\n",
+        test("tests/errors/parameter_too_many_arguments.nm")
+    );
+}
+
+#[test]
+fn parameter_missing_argument() {
+    assert_eq!(
+        "Error: tests/errors/parameter_missing_argument.nm:13:5: missing argument for parameter \
+         resistance of component R\n",
+        test("tests/errors/parameter_missing_argument.nm")
+    );
+}
+
+#[test]
+fn parameter_type_mismatch() {
+    assert_eq!(
+        "Error: tests/errors/parameter_type_mismatch.nm:13:5: parameter resistance of component R \
+         must be a string, but was given a number\n",
+        test("tests/errors/parameter_type_mismatch.nm")
+    );
+}
+
+#[test]
+fn parameter_unknown_reference() {
+    assert_eq!(
+        "Error: tests/errors/parameter_unknown_reference.nm:13:5: cannot find parameter named ohms \
+         in instantiation of component R\n",
+        test("tests/errors/parameter_unknown_reference.nm")
+    );
+}
+
+#[test]
+fn instance_array_width() {
+    assert_eq!(
+        "Error: tests/errors/instance_array_width.nm:9:1: error in component Main\nCaused by: \
+         tests/errors/instance_array_width.nm:12:5: expected 1 or 4 connections on the right, but \
+         found 3\n",
+        test("tests/errors/instance_array_width.nm")
+    );
+}
+
+#[test]
+fn erc_instance_array_error() {
+    assert_eq!(
+        "Error: tests/errors/erc_instance_array_error.nm:11:5: in instantiation of Foo[0], pin A \
+         (PowerOut) is connected by net VCC to pin A (PowerOut) of instantiation Foo[1] at \
+         tests/errors/erc_instance_array_error.nm:11:5\n",
+        test("tests/errors/erc_instance_array_error.nm")
+    );
+}
+
+#[test]
+fn bundle_unknown() {
+    assert_eq!(
+        "Error: tests/errors/bundle_unknown.nm:9:5: cannot find bundle named Spi\n",
+        test("tests/errors/bundle_unknown.nm")
+    );
+}
+
+#[test]
+fn bundle_type_mismatch() {
+    assert_eq!(
+        "Error: tests/errors/bundle_type_mismatch.nm:29:5: cannot connect I2C bundle pin bus of \
+         Wrapper to serial, which is a Uart bundle\nError: \
+         tests/errors/bundle_type_mismatch.nm:30:5: cannot connect I2C bundle pin bus of Wrapper \
+         to serial, which is a Uart bundle\n",
+        test("tests/errors/bundle_type_mismatch.nm")
+    );
+}
+
+#[test]
+fn if_unknown_parameter() {
+    assert_eq!(
+        "Error: tests/errors/if_unknown_parameter.nm:12:5: cannot find parameter named pull for \
+         if block in component Pull\n",
+        test("tests/errors/if_unknown_parameter.nm")
+    );
+}
+
+#[test]
+fn for_unknown_variable() {
+    assert_eq!(
+        "Error: tests/errors/for_unknown_variable.nm:9:1: error in component Main\nCaused by: \
+         tests/errors/for_unknown_variable.nm:13:5: D[j] is indexed by j, which isn't a for \
+         loop variable\n",
+        test("tests/errors/for_unknown_variable.nm")
+    );
+}
+
+#[test]
+fn duplicate_instance_name() {
+    assert_eq!(
+        "Error: tests/errors/duplicate_instance_name.nm:13:5: instance name r_pullup is used \
+         more than once in component Main\n",
+        test("tests/errors/duplicate_instance_name.nm")
+    );
+}
+
+#[test]
+fn duplicate_instance_name_rendered() {
+    assert_eq!(
+        "error: instance name r_pullup is used more than once in component Main
+  --> tests/errors/duplicate_instance_name.nm:13:5
+   |
+13 |     R r_pullup { A: X; B: Y; }
+   |     ^^^^^^^^^^
+   |
+12 |     R r_pullup { A: X; B: Y; }
+   |     ---------- first used here
+",
+        test_rendered("tests/errors/duplicate_instance_name.nm")
+    );
+}
+
+#[test]
+fn duplicate_pin_json() {
+    assert_eq!(
+        "{\"severity\":\"error\",\"code\":\"component\",\"message\":\"error in component Main\",\
+         \"span\":{\"file\":\"tests/errors/duplicate_pin.nm\",\"line\":1,\"col\":1,\"end_line\":1,\
+         \"end_col\":24},\"labels\":[],\"causes\":[{\"severity\":\"error\",\"code\":\"duplicate\",\
+         \"message\":\"duplicate pin named FOO\",\
+         \"span\":{\"file\":\"tests/errors/duplicate_pin.nm\",\"line\":3,\"col\":5,\
+         \"end_line\":3,\"end_col\":20},\"labels\":[]}]}\n",
+        test_formatted("tests/errors/duplicate_pin.nm", MessageFormat::Json)
+    );
+}
+
+#[test]
+fn duplicate_instance_name_sarif() {
+    let sarif = test_formatted("tests/errors/duplicate_instance_name.nm", MessageFormat::Sarif);
+    let log: serde_json::Value = serde_json::from_str(&sarif).unwrap();
+    assert_eq!("2.1.0", log["version"]);
+    let result = &log["runs"][0]["results"][0];
+    assert_eq!("duplicate", result["ruleId"]);
+    assert_eq!("error", result["level"]);
+    let location = &result["locations"][0]["physicalLocation"];
+    assert_eq!(
+        "tests/errors/duplicate_instance_name.nm",
+        location["artifactLocation"]["uri"]
+    );
+    assert_eq!(13, location["region"]["startLine"]);
+    assert_eq!(5, location["region"]["startColumn"]);
+    assert_eq!(
+        12,
+        result["relatedLocations"][0]["physicalLocation"]["region"]["startLine"]
+    );
+}
+
+#[test]
+fn field_on_abstract_instance() {
+    assert_eq!(
+        "Error: tests/errors/field_on_abstract_instance.nm:18:5: cannot set field mpn on \
+         instance of abstract component Divider\n",
+        test("tests/errors/field_on_abstract_instance.nm")
+    );
+}
+
+#[test]
+fn pin_range_mismatch() {
+    assert_eq!(
+        "Error: tests/errors/pin_range_mismatch.nm:5:40: pin number range (A1..B2) must have the \
+         same letters followed by numbers on both ends\n",
+        test("tests/errors/pin_range_mismatch.nm")
+    );
+}
+
+#[test]
+fn duplicate_pad() {
+    assert_eq!(
+        "Error: tests/errors/duplicate_pad.nm:1:1: error in component Foo\nCaused by: \
+         tests/errors/duplicate_pad.nm:6:5: pin number 2 assigned to GND more than once\n",
+        test("tests/errors/duplicate_pad.nm")
+    );
+}
+
+#[test]
+fn extends_cycle() {
+    assert_eq!(
+        "Error: tests/errors/extends_cycle.nm:1:1: component A extends itself: A extends B at \
+         tests/errors/extends_cycle.nm:1:1, B extends A at tests/errors/extends_cycle.nm:5:1\n",
+        test("tests/errors/extends_cycle.nm")
+    );
+}
+
+#[test]
+fn extends_self() {
+    assert_eq!(
+        "Error: tests/errors/extends_self.nm:5:1: component B extends itself: B extends B at \
+         tests/errors/extends_self.nm:5:1\n",
+        test("tests/errors/extends_self.nm")
+    );
+}
+
+#[test]
+fn extends_with_pins() {
+    assert_eq!(
+        "Error: tests/errors/extends_with_pins.nm:8:1: component Variant extends Base, so it \
+         cannot declare its own pins\n",
+        test("tests/errors/extends_with_pins.nm")
+    );
+}
+
+#[test]
+fn unknown_package() {
+    assert_eq!(
+        "Error: tests/errors/unknown_package.nm:20:5: component Regulator has no package named \
+         dpak\n",
+        test("tests/errors/unknown_package.nm")
+    );
+}
+
+#[test]
+fn private_component() {
+    assert_eq!(
+        "Error: tests/errors/namespaces/private_component.nm:6:5: component Helper is private \
+         to tests/errors/namespaces/lib.nm\n",
+        test("tests/errors/namespaces/private_component.nm")
+    );
+}
+
+#[test]
+fn unknown_namespace() {
+    assert_eq!(
+        "Error: tests/errors/namespaces/unknown_namespace.nm:6:5: cannot find namespace ti for \
+         ti::Helper\n",
+        test("tests/errors/namespaces/unknown_namespace.nm")
+    );
+}
+
+#[test]
+fn lib_path_missing_module() {
+    assert_eq!(
+        "Error: tests/errors/lib_paths/missing_module.nm:1:1: cannot find file named \
+         \"passives.nm\" (tried tests/errors/lib_paths/passives.nm, \
+         tests/errors/lib_paths/libs/passives.nm, tests/errors/lib_paths/vendor/passives.nm)\n",
+        test("tests/errors/lib_paths/missing_module.nm")
+    );
+}
+
+#[test]
+fn erc_rule_unknown() {
+    assert_eq!(
+        "Error: tests/errors/erc_rule_unknown/netmuncher.toml:2:1: unknown ERC rule \
+         output-open_drain, which should name two pin types like output-tristate\n",
+        test("tests/errors/erc_rule_unknown/main.nm")
+    );
+}
+
+#[test]
+fn manifest_settings() {
+    assert_eq!(
+        "Error: tests/errors/manifest_settings/netmuncher.toml:5:1: unknown lint unused-wire\n\
+         Error: tests/errors/manifest_settings/netmuncher.toml:11:1: duplicate board named \
+         card\nError: tests/errors/manifest_settings/netmuncher.toml:15:1: unknown output \
+         format gerber for build/main.gbr\n",
+        test("tests/errors/manifest_settings/main.nm")
+    );
+}
+
+#[test]
+fn manifest_syntax() {
+    assert_eq!(
+        "Error: tests/errors/manifest_syntax/netmuncher.toml:4:14: invalid number\n",
+        test("tests/errors/manifest_syntax/main.nm")
+    );
+}
+
+#[test]
+fn duplicate_global_nets() {
+    assert_eq!(
+        "Error: tests/errors/duplicate_global_nets.nm:7:8: global net GND is declared more \
+         than once\n",
+        test("tests/errors/duplicate_global_nets.nm")
+    );
+}
+
+#[test]
+fn top_with_pins() {
+    assert_eq!(
+        "Error: tests/errors/top_with_pins/main.nm:1:1: component Board cannot have pins\n",
+        test("tests/errors/top_with_pins/main.nm")
+    );
+}
+
+#[test]
+fn system_unconnected_pin() {
+    assert_eq!(
+        "Error: tests/errors/system/unconnected_pin/netmuncher.toml:11:1: pin 3 of \
+         backplane.J1 is connected to net C, but pin 3 of card.J1 isn't connected\n",
+        test_system("tests/errors/system/unconnected_pin/netmuncher.toml")
+    );
+}
+
+#[test]
+fn system_erc() {
+    assert_eq!(
+        "Error: tests/errors/system/erc/netmuncher.toml:11:1: pin OUT (Output) of \
+         backplane.U1 on net B is connected across boards to pin OUT (Output) of card.U1 on \
+         net B\nError: tests/errors/system/erc/netmuncher.toml:11:1: pin OUT (Output) of \
+         backplane.U2 on net C is connected across boards to pin OUT (Output) of card.U2 on \
+         net C\n",
+        test_system("tests/errors/system/erc/netmuncher.toml")
+    );
+}
+
+#[test]
+fn system_link_pins() {
+    assert_eq!(
+        "Error: tests/errors/system/link_pins/netmuncher.toml:12:1: pin 3 of backplane.J1 is \
+         connected to net C, but the link's pins leave it out\nError: \
+         tests/errors/system/link_pins/netmuncher.toml:12:1: pin 3 of card.J1 is connected to \
+         net C, but the link's pins leave it out\n",
+        test_system("tests/errors/system/link_pins/netmuncher.toml")
+    );
+}
+
+#[test]
+fn system_short() {
+    assert_eq!(
+        "Error: tests/errors/system/short/netmuncher.toml:16:1: links join nets A and B of \
+         board backplane\n",
+        test_system("tests/errors/system/short/netmuncher.toml")
+    );
+}
+
+#[test]
+fn multiple_errors() {
+    assert_eq!(
+        "Error: tests/errors/multiple_errors.nm:21:5: in instantiation of Driver, pin OUT \
+         (Output) is connected by net BUS to pin OUT (Output) of instantiation Driver at \
+         tests/errors/multiple_errors.nm:22:5\n\
+         Error: tests/errors/multiple_errors.nm:23:5: cannot find component definition for \
+         Regulator\n\
+         Error: tests/errors/multiple_errors.nm:24:5: no connection stated for pin B on \
+         component Resistor\n\
+         Error: tests/errors/multiple_errors.nm:27:1: concrete component Capacitor must specify \
+         a footprint\n",
+        test("tests/errors/multiple_errors.nm")
+    );
+}
+
+#[test]
+fn denied_warning() {
+    assert_eq!(
+        "Error: tests/errors/denied_warning.nm:18:5: in instantiation of Driver, pin OUT \
+         (Output) is connected by net BUS to pin Q (Tristate) of instantiation Latch at \
+         tests/errors/denied_warning.nm:19:5\n",
+        test_denied("tests/errors/denied_warning.nm")
+    );
+}
+
+#[test]
+fn denied_warnings() {
+    // denied warnings are sorted by where they were found, like other errors
+    assert_eq!(
+        "Error: tests/errors/denied_warnings.nm:12:5: pin SPARE of component Pair isn't \
+         connected to anything inside it\nError: tests/errors/denied_warnings.nm:14:5: net \
+         SPARE_NET of component Pair isn't connected to anything\n",
+        test_denied("tests/errors/denied_warnings.nm")
+    );
+}
+
+#[test]
+fn allow_label() {
+    // the allow comment only points at the label of the warning, not at the warning itself
+    assert_eq!(
+        "Error: tests/errors/allow_label.nm:18:5: in instantiation of Driver, pin OUT \
+         (Output) is connected by net BUS to pin Q (Tristate) of instantiation Latch at \
+         tests/errors/allow_label.nm:20:5\n",
+        test_denied("tests/errors/allow_label.nm")
+    );
+}
+
+#[test]
+fn unclosed_allow() {
+    assert_eq!(
+        "Error: tests/errors/unclosed_allow.nm:18:5: expected ')' to close netmuncher:allow\n",
+        test("tests/errors/unclosed_allow.nm")
+    );
+}
+
+#[test]
+fn erc_block() {
+    assert_eq!(
+        "Error: tests/errors/erc_block.nm:22:5: in instantiation of Driver, pin OUT (Output) is \
+         connected by net BUS to pin Q (Tristate) of instantiation Latch at \
+         tests/errors/erc_block.nm:23:5\n",
+        test("tests/errors/erc_block.nm")
+    );
+}
+
+#[test]
+fn erc_mapping() {
+    assert_eq!(
+        "Error: tests/errors/erc_mapping/main.nm:11:5: in instantiation of Buffer, pin IN (Input) \
+         mapped to Q (Tristate)\n",
+        test("tests/errors/erc_mapping/main.nm")
+    );
+}
+
+#[test]
+fn erc_hierarchy() {
+    assert_eq!(
+        "Error: tests/errors/erc_hierarchy.nm:13:5: pin VOUT (PowerOut) of Main1/main/U1 is \
+         connected by net RAIL to pin VOUT (PowerOut) of Main1/backup/U2 at \
+         tests/errors/erc_hierarchy.nm:13:5\n",
+        test("tests/errors/erc_hierarchy.nm")
+    );
+}
+
+#[test]
+fn undriven_input() {
+    assert_eq!(
+        "Error: tests/errors/undriven_input.nm:17:5: nothing drives net A, which is connected \
+         to pin IN1 (Input) of Main1/U1, pin IN2 (Input) of Main1/U1\n",
+        test("tests/errors/undriven_input.nm")
+    );
+}
+
+#[test]
+fn voltage_mismatch() {
+    assert_eq!(
+        "Error: tests/errors/voltage_mismatch.nm:23:5: pin VDD (PowerIn) of Main1/U1 accepts 3V \
+         to 3.6V, but net VCC is at 5V from global net VCC\n\
+         Error: tests/errors/voltage_mismatch.nm:23:5: pin EN (Input) of Main1/U1 accepts 1.8V \
+         to 3.6V, but net VCC is at 5V from global net VCC\n",
+        test("tests/errors/voltage_mismatch.nm")
+    );
+}
+
+#[test]
+fn rail_short() {
+    assert_eq!(
+        "Error: tests/errors/rail_short.nm:2:8: net VCC shorts rails of different voltages \
+         together: 5V from global net VCC, 3.3V from pin OUT of Main1/U1\n",
+        test("tests/errors/rail_short.nm")
+    );
+}
+
+#[test]
+fn output_voltage() {
+    assert_eq!(
+        "Error: tests/errors/output_voltage.nm:5:14: only power_out pins can supply a voltage\n",
+        test("tests/errors/output_voltage.nm")
+    );
+}
+
+#[test]
+fn instantiation_cycle() {
+    assert_eq!(
+        "Error: tests/errors/instantiation_cycle.nm:4:5: component Outer instantiates itself: \
+         Outer instantiates Inner at tests/errors/instantiation_cycle.nm:4:5, Inner instantiates \
+         Outer at tests/errors/instantiation_cycle.nm:10:5\n",
+        test("tests/errors/instantiation_cycle.nm")
+    );
+}
+
+#[test]
+fn self_instance() {
+    assert_eq!(
+        "Error: tests/errors/self_instance.nm:4:5: component Chain instantiates itself: Chain \
+         instantiates Chain at tests/errors/self_instance.nm:4:5\n",
+        test("tests/errors/self_instance.nm")
+    );
+}
+
+#[test]
+fn lint_deny() {
+    assert_eq!(
+        "Error: tests/errors/lint_deny/main.nm:12:5: pin SPARE of component Pair isn't connected \
+         to anything inside it\n",
+        test("tests/errors/lint_deny/main.nm")
+    );
+}
@@ -12,8 +12,16 @@ extern crate netmuncher;
 
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
 use netmuncher::circuit::{Circuit, KicadNetListSerializer, SerializeCircuit};
+use netmuncher::deps::Dependencies;
+use netmuncher::diagnostic::Severity;
+use netmuncher::erc::ErcMatrix;
+use netmuncher::lint::{Lint, LintLevel};
+use netmuncher::manifest::Manifest;
+use netmuncher::options::Options;
+use netmuncher::system::System;
 
 fn load(file_name: &str) -> String {
     let mut file = File::open(file_name).unwrap();
@@ -28,6 +36,23 @@ fn compile(file_name: &str) -> String {
     String::from_utf8(serialized).unwrap()
 }
 
+fn compile_with_options(file_name: &str, options: &Options) -> String {
+    let circuit = Circuit::compile_with_options(file_name, options).unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    String::from_utf8(serialized).unwrap()
+}
+
+fn compile_with_warnings(file_name: &str) -> (String, Vec<(Severity, String, String)>) {
+    let circuit = Circuit::compile(file_name).unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    let warnings = circuit
+        .warnings
+        .iter()
+        .map(|w| (w.severity, w.code.clone(), w.to_string()))
+        .collect();
+    (String::from_utf8(serialized).unwrap(), warnings)
+}
+
 #[test]
 fn nand_indicator() {
     let expected = load("tests/valid/nand_indicator.net");
@@ -76,3 +101,322 @@ fn logic_switch() {
     let actual = compile("tests/valid/logic_switch.nm");
     assert_eq!(expected, actual);
 }
+
+#[test]
+fn parameters() {
+    let expected = load("tests/valid/parameters.net");
+    let actual = compile("tests/valid/parameters.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn instance_arrays() {
+    let expected = load("tests/valid/instance_arrays.net");
+    let actual = compile("tests/valid/instance_arrays.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn bundles() {
+    let expected = load("tests/valid/bundles.net");
+    let actual = compile("tests/valid/bundles.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn generate() {
+    let expected = load("tests/valid/generate.net");
+    let actual = compile("tests/valid/generate.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn for_if() {
+    let expected = load("tests/valid/for_if.net");
+    let actual = compile("tests/valid/for_if.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn if_blocks() {
+    let expected = load("tests/valid/if_blocks.net");
+    let actual = compile("tests/valid/if_blocks.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn named_instances() {
+    let expected = load("tests/valid/named_instances.net");
+    let actual = compile("tests/valid/named_instances.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn named_instances_inserted() {
+    // nets of named instances keep their names when another instance takes the same name
+    let net_names = |netlist: &str| -> Vec<String> {
+        netlist
+            .lines()
+            .filter(|line| line.contains("(net (code"))
+            .map(|line| line.split("(name ").nth(1).unwrap().to_string())
+            .collect()
+    };
+    let inserted = net_names(&compile("tests/valid/named_instances_inserted.nm"));
+    for name in net_names(&load("tests/valid/named_instances.net")) {
+        assert!(inserted.contains(&name), "net {} was renamed", name);
+    }
+}
+
+#[test]
+fn fields() {
+    let expected = load("tests/valid/fields.net");
+    let actual = compile("tests/valid/fields.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn alphanumeric_pins() {
+    let expected = load("tests/valid/alphanumeric_pins.net");
+    let actual = compile("tests/valid/alphanumeric_pins.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn multiple_pads() {
+    let expected = load("tests/valid/multiple_pads.net");
+    let actual = compile("tests/valid/multiple_pads.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn extends() {
+    let expected = load("tests/valid/extends.net");
+    let actual = compile("tests/valid/extends.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn packages() {
+    let expected = load("tests/valid/packages.net");
+    let actual = compile("tests/valid/packages.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn namespaces() {
+    let expected = load("tests/valid/namespaces/main.net");
+    let actual = compile("tests/valid/namespaces/main.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn lib_paths() {
+    let expected = load("tests/valid/lib_paths/project/main.net");
+    let actual = compile("tests/valid/lib_paths/project/main.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn manifest() {
+    let manifest = Manifest::load("tests/valid/manifest/netmuncher.toml").unwrap();
+    assert_eq!(
+        Path::new("tests/valid/manifest/main.nm"),
+        manifest.entry_path().unwrap()
+    );
+    let outputs: Vec<(String, PathBuf)> = manifest
+        .outputs()
+        .into_iter()
+        .map(|output| (output.format, output.path))
+        .collect();
+    assert_eq!(
+        vec![
+            ("kicad".into(), PathBuf::from("tests/valid/manifest/build/main.net")),
+            ("dot".into(), PathBuf::from("tests/valid/manifest/build/main.dot")),
+        ],
+        outputs
+    );
+
+    // the manifest's ERC override allows the buffers to drive the same net
+    let expected = load("tests/valid/manifest/main.net");
+    let actual = compile("tests/valid/manifest/main.nm");
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn erc_warning() {
+    let (actual, warnings) = compile_with_warnings("tests/valid/erc_warning.nm");
+    assert_eq!(load("tests/valid/erc_warning.net"), actual);
+    assert_eq!(
+        vec![
+            (
+                Severity::Warning,
+                "erc-output-tristate".into(),
+                "tests/valid/erc_warning.nm:18:5: in instantiation of Driver, pin OUT (Output) is \
+                 connected by net BUS to pin Q (Tristate) of instantiation Latch at \
+                 tests/valid/erc_warning.nm:19:5"
+                    .into(),
+            ),
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn erc_hierarchy() {
+    let (actual, warnings) = compile_with_warnings("tests/valid/erc_hierarchy.nm");
+    assert_eq!(load("tests/valid/erc_hierarchy.net"), actual);
+    assert_eq!(
+        vec![
+            (
+                Severity::Warning,
+                "erc-output-tristate".into(),
+                "tests/valid/erc_hierarchy.nm:18:5: pin OUT (Output) of Main1/Stage1/U1 is \
+                 connected by net BUS to pin Q (Tristate) of Main1/U2 at \
+                 tests/valid/erc_hierarchy.nm:25:5"
+                    .into(),
+            ),
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn net_drivers() {
+    let (actual, warnings) = compile_with_warnings("tests/valid/net_drivers.nm");
+    assert_eq!(load("tests/valid/net_drivers.net"), actual);
+    assert_eq!(
+        vec![
+            (
+                Severity::Warning,
+                "undriven-input".into(),
+                "tests/valid/net_drivers.nm:37:5: nothing drives net UNDRIVEN, which is \
+                 connected to pin IN2 (Input) of Main1/U2"
+                    .into(),
+            ),
+            (
+                Severity::Warning,
+                "floating-net".into(),
+                "tests/valid/net_drivers.nm:39:5: net PAD only connects passive pins, none of \
+                 which lead to anything driven: pin TP (Passive) of Main1/TP2, pin TP (Passive) \
+                 of Main1/TP3"
+                    .into(),
+            ),
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn power_domains() {
+    let circuit = Circuit::compile("tests/valid/power_domains.nm").unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    assert_eq!(
+        load("tests/valid/power_domains.net"),
+        String::from_utf8(serialized).unwrap()
+    );
+    assert!(circuit.warnings.is_empty());
+}
+
+#[test]
+fn erc_allow() {
+    let circuit = Circuit::compile("tests/valid/erc_allow.nm").unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    assert_eq!(
+        load("tests/valid/erc_allow.net"),
+        String::from_utf8(serialized).unwrap()
+    );
+    assert!(circuit.warnings.is_empty());
+}
+
+#[test]
+fn erc_block() {
+    let circuit = Circuit::compile("tests/valid/erc_block.nm").unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    assert_eq!(
+        load("tests/valid/erc_block.net"),
+        String::from_utf8(serialized).unwrap()
+    );
+    assert!(circuit.warnings.is_empty());
+
+    let matrix = ErcMatrix::for_file("tests/valid/erc_block.nm", &Options::new()).unwrap();
+    assert_eq!(load("tests/valid/erc_block.txt"), matrix.report());
+}
+
+#[test]
+fn lints() {
+    let mut options = Options::new();
+    options
+        .lint_levels
+        .push((Lint::UnusedComponent, LintLevel::Warn));
+    let circuit = Circuit::compile_with_options("tests/valid/lints.nm", &options).unwrap();
+    let serialized = KicadNetListSerializer::new().serialize(&circuit).unwrap();
+    assert_eq!(
+        load("tests/valid/lints.net"),
+        String::from_utf8(serialized).unwrap()
+    );
+    let warnings: Vec<(String, String)> = circuit
+        .warnings
+        .iter()
+        .map(|w| (w.code.clone(), w.to_string()))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "unused-component".into(),
+                "tests/valid/lints.nm:13:1: component Led is never used by Main".into(),
+            ),
+            (
+                "unused-component".into(),
+                "tests/valid/lints.nm:30:1: component Spare is never used by Main".into(),
+            ),
+            (
+                "unused-pin".into(),
+                "tests/valid/lints.nm:24:5: pin SENSE of component Divider isn't connected to \
+                 anything inside it"
+                    .into(),
+            ),
+            (
+                "redundant-value".into(),
+                "tests/valid/lints.nm:27:5: value Resistor is the same as the default value of \
+                 Resistor"
+                    .into(),
+            ),
+            (
+                "unused-net".into(),
+                "tests/valid/lints.nm:31:5: net FLOAT of component Spare isn't connected to \
+                 anything"
+                    .into(),
+            ),
+        ],
+        warnings
+    );
+}
+
+#[test]
+fn deps() {
+    let deps = Dependencies::for_file("tests/valid/deps.nm", &Options::new()).unwrap();
+    assert_eq!(load("tests/valid/deps.txt"), deps.report());
+}
+
+#[test]
+fn top() {
+    let mut options = Options::new();
+    options.top = Some("Fixture".into());
+    let expected = load("tests/valid/top.net");
+    let actual = compile_with_options("tests/valid/top.nm", &options);
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn system() {
+    let manifest = Manifest::load("tests/valid/system/netmuncher.toml").unwrap();
+    let system = System::compile(&manifest, &Options::new()).unwrap();
+    for board in &system.boards {
+        let expected = load(&format!("tests/valid/system/{}.net", board.name));
+        let serialized = KicadNetListSerializer::new()
+            .serialize(&board.circuit)
+            .unwrap();
+        assert_eq!(expected, String::from_utf8(serialized).unwrap());
+    }
+    assert_eq!(load("tests/valid/system/wiring.txt"), system.wiring_report());
+}